@@ -256,7 +256,9 @@ pub mod state {
 
 pub mod message {
     use super::*;
-    use canistore_types::message::{MessageType, MsgShareTrack, MsgUserInfo, MsgUserPost};
+    use canistore_types::message::{
+        MessageType, MsgFollowSpace, MsgShareTrack, MsgUserInfo, MsgUserPost,
+    };
     use ic_cdk::print;
     pub fn get_message_size() -> (Vec<(String, usize)>, usize) {
         MESSAGE_STORE.with(|store| {
@@ -447,6 +449,15 @@ pub mod message {
                 // Use the utility function to handle operations
                 handle_message_operation(&msg.msg_type, "MsgShareTrack", &msg_id, &msg, caller).await?;
             }
+            "MsgFollowSpace" => {
+                let follow_space: MsgFollowSpace = msg.decode_payload()?;
+                print(format!(
+                    "Received space follow for id {}: {:?}",
+                    &msg_id, follow_space
+                ));
+                // Use the utility function to handle operations
+                handle_message_operation(&msg.msg_type, "MsgFollowSpace", &msg_id, &msg, caller).await?;
+            }
             _ => {
                 return Err(format!(
                     "Unknown payload_type for id {}: {}",
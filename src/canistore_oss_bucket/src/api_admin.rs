@@ -1,5 +1,6 @@
 use candid::Principal;
-use canistore_types::bucket::UpdateBucketInput;
+use canistore_types::bucket::{SnapshotCursor, SnapshotPage, UpdateBucketInput};
+use serde_bytes::ByteBuf;
 use std::collections::BTreeSet;
 
 use crate::guards::admin_guard;
@@ -45,6 +46,23 @@ fn validate_admin_set_auditors(args: BTreeSet<Principal>) -> Result<(), String>
     Ok(())
 }
 
+#[ic_cdk::update(guard = "admin_guard")]
+fn admin_set_reporters(args: BTreeSet<Principal>) -> Result<(), String> {
+    validate_admin_set_reporters(args.clone())?;
+    store::state::with_mut(|r| {
+        r.reporters = args;
+    });
+    Ok(())
+}
+
+#[ic_cdk::update]
+fn validate_admin_set_reporters(args: BTreeSet<Principal>) -> Result<(), String> {
+    if args.contains(&ANONYMOUS) {
+        return Err("anonymous user is not allowed".to_string());
+    }
+    Ok(())
+}
+
 #[ic_cdk::update(guard = "admin_guard")]
 fn admin_update_bucket(args: UpdateBucketInput) -> Result<(), String> {
     args.validate()?;
@@ -79,6 +97,15 @@ fn admin_update_bucket(args: UpdateBucketInput) -> Result<(), String> {
         if let Some(trusted_eddsa_pub_keys) = args.trusted_eddsa_pub_keys {
             s.trusted_eddsa_pub_keys = trusted_eddsa_pub_keys;
         }
+        if let Some(allowed_origins) = args.allowed_origins {
+            s.allowed_origins = allowed_origins;
+        }
+        if let Some(allowed_headers) = args.allowed_headers {
+            s.allowed_headers = allowed_headers;
+        }
+        if let Some(exposed_headers) = args.exposed_headers {
+            s.exposed_headers = exposed_headers;
+        }
     });
     Ok(())
 }
@@ -87,3 +114,60 @@ fn admin_update_bucket(args: UpdateBucketInput) -> Result<(), String> {
 fn validate_admin_update_bucket(args: UpdateBucketInput) -> Result<(), String> {
     args.validate()
 }
+
+#[ic_cdk::query(guard = "admin_guard")]
+fn admin_export_snapshot(cursor: Option<SnapshotCursor>) -> Result<SnapshotPage, String> {
+    Ok(store::snapshot::export(
+        cursor.unwrap_or(SnapshotCursor::Bucket),
+    ))
+}
+
+#[ic_cdk::update(guard = "admin_guard")]
+fn admin_import_snapshot(data: ByteBuf) -> Result<(), String> {
+    store::snapshot::import(&data)
+}
+
+// pushes the snapshot pages to the target bucket. the target's admin_guard must accept this
+// canister, so it should have this canister as its controller or manager_canister; it is checked
+// before the first page because the bucket page clears the target's files.
+// returns the cursor to continue with if not completed.
+#[ic_cdk::update(guard = "admin_guard")]
+async fn admin_push_snapshot(
+    target: Principal,
+    cursor: Option<SnapshotCursor>,
+    max_pages: Option<u32>,
+) -> Result<Option<SnapshotCursor>, String> {
+    if target == ANONYMOUS || target == ic_cdk::id() {
+        return Err("invalid target bucket".to_string());
+    }
+
+    let (res,): (Result<SnapshotPage, String>,) = ic_cdk::call(
+        target,
+        "admin_export_snapshot",
+        (Some(SnapshotCursor::Bucket),),
+    )
+    .await
+    .map_err(|(code, msg)| format!("failed to check target bucket: {:?}, {}", code, msg))?;
+    res.map_err(|err| {
+        format!(
+            "target bucket does not accept this canister as admin: {}",
+            err
+        )
+    })?;
+
+    let mut cursor = Some(cursor.unwrap_or(SnapshotCursor::Bucket));
+    for _ in 0..max_pages.unwrap_or(10).clamp(1, 100) {
+        let c = match cursor {
+            Some(c) => c,
+            None => break,
+        };
+        let page = store::snapshot::export(c.clone());
+        let (res,): (Result<(), String>,) =
+            ic_cdk::call(target, "admin_import_snapshot", (page.data,))
+                .await
+                .map_err(|(code, msg)| format!("failed to push snapshot: {:?}, {}", code, msg))?;
+        res.map_err(|err| format!("failed to import snapshot at {:?}: {}", c, err))?;
+        cursor = page.next;
+    }
+    Ok(cursor)
+}
@@ -1,7 +1,10 @@
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use candid::{define_function, CandidType};
 use canistore_types::{
-    file::{UrlFileParam, CHUNK_SIZE, MAX_FILE_SIZE_PER_CALL},
+    file::{
+        CreateFileInput, UpdateFileChunkInput, UrlFileParam, UrlUploadParam, CHUNK_SIZE,
+        MAX_FILE_SIZE_PER_CALL,
+    },
     to_cbor_bytes,
 };
 use hyperx::header::{Charset, ContentDisposition, DispositionParam, DispositionType};
@@ -14,7 +17,7 @@ use serde_bytes::ByteBuf;
 use std::path::Path;
 use std::str::FromStr;
 
-use crate::{permission, store, SECONDS};
+use crate::{api_update, permission, store, MILLISECONDS, SECONDS};
 
 #[derive(CandidType, Deserialize, Clone, Default)]
 pub struct HttpStreamingResponse {
@@ -77,6 +80,10 @@ fn create_strategy(arg: StreamingCallbackToken) -> Option<StreamingStrategy> {
 static OCTET_STREAM: &str = "application/octet-stream";
 static IC_CERTIFICATE_HEADER: &str = "ic-certificate";
 static IC_CERTIFICATE_EXPRESSION_HEADER: &str = "ic-certificateexpression";
+static CORS_EXPOSE_HEADERS: &str =
+    "accept-ranges, content-disposition, content-length, content-range, etag";
+static CORS_ALLOW_METHODS: &str = "GET, HEAD, OPTIONS, POST, PUT";
+static CORS_ALLOW_HEADERS: &str = "content-type, if-range, range";
 
 // request url example:
 // https://mmrxu-fqaaa-aaaap-ahhna-cai.icp0.io/f/1
@@ -85,6 +92,15 @@ static IC_CERTIFICATE_EXPRESSION_HEADER: &str = "ic-certificateexpression";
 // TODO: 1. support range request; 2. token verification; 3. cache control
 #[ic_cdk::query(hidden = true)]
 fn http_request(request: HttpRequest) -> HttpStreamingResponse {
+    // uploads are handled by http_request_update
+    if is_upload_method(&request.method) {
+        return HttpStreamingResponse {
+            status_code: 200,
+            upgrade: Some(true),
+            ..Default::default()
+        };
+    }
+
     let witness = store::state::http_tree_with(|t| {
         t.witness(&store::state::DEFAULT_CERT_ENTRY, &request.url)
             .expect("get witness failed")
@@ -110,6 +126,16 @@ fn http_request(request: HttpRequest) -> HttpStreamingResponse {
         ),
     ];
 
+    if request.method.eq_ignore_ascii_case("OPTIONS") {
+        headers.extend(cors_headers(&request.headers, true));
+        return HttpStreamingResponse {
+            status_code: 204,
+            headers,
+            ..Default::default()
+        };
+    }
+    headers.extend(cors_headers(&request.headers, false));
+
     match UrlFileParam::from_url(&request.url) {
         Err(err) => HttpStreamingResponse {
             status_code: 400,
@@ -125,12 +151,14 @@ fn http_request(request: HttpRequest) -> HttpStreamingResponse {
             };
 
             let canister = ic_cdk::id();
+            let parent = store::fs::get_file(id).map(|file| file.parent);
             let ctx = match store::state::with(|s| {
                 s.read_permission(
                     ic_cdk::caller(),
                     &canister,
                     param.token,
                     ic_cdk::api::time() / SECONDS,
+                    parent,
                 )
             }) {
                 Ok(ctx) => ctx,
@@ -274,6 +302,186 @@ fn http_request(request: HttpRequest) -> HttpStreamingResponse {
     }
 }
 
+// upload request example:
+// curl -X POST --data-binary @song.mp3 -H "content-type: audio/mpeg" "https://mmrxu-fqaaa-aaaap-ahhna-cai.icp0.io/upload?parent=1&filename=song.mp3&token=..."
+// curl -X PUT --data-binary @chunk_1 "https://mmrxu-fqaaa-aaaap-ahhna-cai.icp0.io/f/1/1?token=..."
+#[ic_cdk::update(hidden = true)]
+fn http_request_update(request: HttpRequest) -> HttpStreamingResponse {
+    let mut headers = vec![
+        ("content-type".to_string(), "text/plain".to_string()),
+        ("x-content-type-options".to_string(), "nosniff".to_string()),
+    ];
+    headers.extend(cors_headers(&request.headers, false));
+
+    let param = match UrlUploadParam::from_url(&request.method, &request.url) {
+        Ok(param) => param,
+        Err(err) => {
+            return HttpStreamingResponse {
+                status_code: 400,
+                headers,
+                body: ByteBuf::from(err.as_bytes()),
+                ..Default::default()
+            };
+        }
+    };
+
+    let now_ms = ic_cdk::api::time() / MILLISECONDS;
+    let canister = ic_cdk::id();
+    let token = match param {
+        UrlUploadParam::Create { ref token, .. } => token.clone(),
+        UrlUploadParam::Chunk { ref token, .. } => token.clone(),
+    };
+    let ctx = match store::state::with(|s| {
+        s.write_permission(ic_cdk::caller(), &canister, token, now_ms / 1000)
+    }) {
+        Ok(ctx) => ctx,
+        Err((status_code, err)) => {
+            return HttpStreamingResponse {
+                status_code,
+                headers,
+                body: ByteBuf::from(err.as_bytes()),
+                ..Default::default()
+            };
+        }
+    };
+
+    let res = match param {
+        UrlUploadParam::Create {
+            parent, name, size, ..
+        } => {
+            let content_type = request
+                .headers
+                .iter()
+                .find_map(|(name, value)| {
+                    if name.to_lowercase() == "content-type" {
+                        Some(value.clone())
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or_else(|| OCTET_STREAM.to_string());
+            let content = if request.body.is_empty() {
+                None
+            } else {
+                Some(ByteBuf::from(request.body.to_vec()))
+            };
+
+            api_update::create_file_with(
+                &ctx,
+                CreateFileInput {
+                    parent,
+                    name,
+                    content_type,
+                    size,
+                    content,
+                    ..Default::default()
+                },
+                now_ms,
+            )
+            .map(|output| serde_json::to_vec(&output).unwrap_or_default())
+        }
+        UrlUploadParam::Chunk {
+            id, chunk_index, ..
+        } => api_update::update_file_chunk_with(
+            &ctx,
+            UpdateFileChunkInput {
+                id,
+                chunk_index,
+                content: ByteBuf::from(request.body.to_vec()),
+                crc32: None,
+            },
+            now_ms,
+        )
+        .map(|output| serde_json::to_vec(&output).unwrap_or_default()),
+    };
+
+    match res {
+        Ok(body) => {
+            headers[0].1 = "application/json".to_string();
+            HttpStreamingResponse {
+                status_code: 200,
+                headers,
+                body: ByteBuf::from(body),
+                ..Default::default()
+            }
+        }
+        Err(err) => HttpStreamingResponse {
+            status_code: if err == "permission denied" { 403 } else { 400 },
+            headers,
+            body: ByteBuf::from(err.as_bytes()),
+            ..Default::default()
+        },
+    }
+}
+
+fn is_upload_method(method: &str) -> bool {
+    method.eq_ignore_ascii_case("POST") || method.eq_ignore_ascii_case("PUT")
+}
+
+fn cors_headers(request_headers: &[HeaderField], preflight: bool) -> Vec<HeaderField> {
+    let origin = request_headers.iter().find_map(|(name, value)| {
+        if name.to_lowercase() == "origin" {
+            Some(value.as_str())
+        } else {
+            None
+        }
+    });
+
+    let (allow_origin, allow_headers, expose_headers) = match origin {
+        None => return vec![],
+        Some(origin) => match store::state::with(|s| {
+            allowed_origin(&s.allowed_origins, origin).map(|allow_origin| {
+                (
+                    allow_origin,
+                    cors_header_list(&s.allowed_headers, CORS_ALLOW_HEADERS),
+                    cors_header_list(&s.exposed_headers, CORS_EXPOSE_HEADERS),
+                )
+            })
+        }) {
+            None => return vec![],
+            Some(policy) => policy,
+        },
+    };
+
+    let mut headers = vec![
+        (
+            "access-control-allow-origin".to_string(),
+            allow_origin.clone(),
+        ),
+        ("access-control-expose-headers".to_string(), expose_headers),
+    ];
+    if allow_origin != "*" {
+        headers.push(("vary".to_string(), "origin".to_string()));
+    }
+    if preflight {
+        headers.push((
+            "access-control-allow-methods".to_string(),
+            CORS_ALLOW_METHODS.to_string(),
+        ));
+        headers.push(("access-control-allow-headers".to_string(), allow_headers));
+        headers.push(("access-control-max-age".to_string(), "86400".to_string()));
+    }
+    headers
+}
+
+fn cors_header_list(headers: &[String], default: &str) -> String {
+    if headers.is_empty() {
+        default.to_string()
+    } else {
+        headers.join(", ")
+    }
+}
+
+fn allowed_origin(allowed_origins: &[String], origin: &str) -> Option<String> {
+    if allowed_origins.iter().any(|o| o == "*") {
+        return Some("*".to_string());
+    }
+    allowed_origins
+        .iter()
+        .find(|o| o.eq_ignore_ascii_case(origin))
+        .map(|_| origin.to_string())
+}
+
 #[ic_cdk::query(hidden = true)]
 fn http_request_streaming_callback(token: StreamingCallbackToken) -> StreamingCallbackHttpResponse {
     match store::fs::get_chunk(token.id, token.chunk_index) {
@@ -454,4 +662,29 @@ mod test {
             "attachment; filename=\"test.txt\"",
         );
     }
+
+    #[test]
+    fn test_allowed_origin() {
+        assert_eq!(allowed_origin(&[], "https://canistore.io"), None);
+
+        let origins = vec![
+            "https://canistore.io".to_string(),
+            "http://localhost:3000".to_string(),
+        ];
+        assert_eq!(
+            allowed_origin(&origins, "https://canistore.io"),
+            Some("https://canistore.io".to_string())
+        );
+        assert_eq!(
+            allowed_origin(&origins, "http://localhost:3000"),
+            Some("http://localhost:3000".to_string())
+        );
+        assert_eq!(allowed_origin(&origins, "https://example.com"), None);
+
+        let origins = vec!["*".to_string()];
+        assert_eq!(
+            allowed_origin(&origins, "https://example.com"),
+            Some("*".to_string())
+        );
+    }
 }
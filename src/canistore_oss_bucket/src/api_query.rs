@@ -1,12 +1,13 @@
 use canistore_types::{
     bucket::BucketInfo,
-    file::{FileChunk, FileInfo},
+    file::{FileChunk, FileDailyStat, FileInfo, FileStatsSummary, MAX_FILE_STATS_DAYS},
     folder::{FolderInfo, FolderName},
     ByteN,
 };
 use serde_bytes::ByteBuf;
+use std::collections::BTreeMap;
 
-use crate::{permission, store, SECONDS};
+use crate::{permission, store, MILLISECONDS, SECONDS};
 
 #[ic_cdk::query]
 fn api_version() -> u16 {
@@ -21,7 +22,7 @@ fn get_bucket_info(access_token: Option<ByteBuf>) -> Result<BucketInfo, String>
 
     if !ic_cdk::api::is_controller(&caller) {
         let ctx = store::state::with(|s| {
-            s.read_permission(caller, &canister, access_token, current_time)
+            s.read_permission(caller, &canister, access_token, current_time, None)
         })
         .map_err(|(_, err)| err)?;
 
@@ -48,6 +49,10 @@ fn get_bucket_info(access_token: Option<ByteBuf>) -> Result<BucketInfo, String>
         auditors: r.auditors.clone(),
         trusted_ecdsa_pub_keys: r.trusted_ecdsa_pub_keys.clone(),
         trusted_eddsa_pub_keys: r.trusted_eddsa_pub_keys.clone(),
+        reporters: r.reporters.clone(),
+        allowed_origins: r.allowed_origins.clone(),
+        allowed_headers: r.allowed_headers.clone(),
+        exposed_headers: r.exposed_headers.clone(),
     }))
 }
 
@@ -63,6 +68,7 @@ fn get_file_info(id: u32, access_token: Option<ByteBuf>) -> Result<FileInfo, Str
                     &canister,
                     access_token,
                     ic_cdk::api::time() / SECONDS,
+                    Some(file.parent),
                 )
             }) {
                 Ok(ctx) => ctx,
@@ -101,6 +107,7 @@ fn get_file_ancestors(id: u32, access_token: Option<ByteBuf>) -> Result<Vec<Fold
                 &canister,
                 access_token,
                 ic_cdk::api::time() / SECONDS,
+                Some(parent.id),
             )
         }) {
             Ok(ctx) => ctx,
@@ -133,6 +140,7 @@ fn get_file_chunks(
                     &canister,
                     access_token,
                     ic_cdk::api::time() / SECONDS,
+                    Some(file.parent),
                 )
             }) {
                 Ok(ctx) => ctx,
@@ -170,6 +178,7 @@ fn list_files(
             &canister,
             access_token,
             ic_cdk::api::time() / SECONDS,
+            Some(parent),
         )
     }) {
         Ok(ctx) => ctx,
@@ -196,6 +205,7 @@ fn get_folder_info(id: u32, access_token: Option<ByteBuf>) -> Result<FolderInfo,
                     &canister,
                     access_token,
                     ic_cdk::api::time() / SECONDS,
+                    Some(id),
                 )
             }) {
                 Ok(ctx) => ctx,
@@ -224,6 +234,7 @@ fn get_folder_ancestors(id: u32, access_token: Option<ByteBuf>) -> Result<Vec<Fo
                 &canister,
                 access_token,
                 ic_cdk::api::time() / SECONDS,
+                Some(id),
             )
         }) {
             Ok(ctx) => ctx,
@@ -256,6 +267,7 @@ fn list_folders(
             &canister,
             access_token,
             ic_cdk::api::time() / SECONDS,
+            Some(parent),
         )
     }) {
         Ok(ctx) => ctx,
@@ -269,3 +281,89 @@ fn list_folders(
     }
     Ok(store::fs::list_folders(&ctx, parent, prev, take))
 }
+
+#[ic_cdk::query]
+fn get_file_stats(
+    id: u32,
+    from_day: u32,
+    to_day: Option<u32>,
+    access_token: Option<ByteBuf>,
+) -> Result<Vec<FileDailyStat>, String> {
+    match store::fs::get_file(id) {
+        None => Err("file not found".to_string()),
+        Some(file) => {
+            let now_ms = ic_cdk::api::time() / MILLISECONDS;
+            let to_day = to_day.unwrap_or_else(|| store::analytics::day_of(now_ms));
+            if from_day > to_day {
+                Err("from_day should be less than or equal to to_day".to_string())?;
+            }
+            if to_day - from_day >= MAX_FILE_STATS_DAYS {
+                Err(format!(
+                    "days range should be less than or equal to {}",
+                    MAX_FILE_STATS_DAYS
+                ))?;
+            }
+
+            let canister = ic_cdk::id();
+            let ctx = match store::state::with(|s| {
+                s.read_permission(
+                    ic_cdk::caller(),
+                    &canister,
+                    access_token,
+                    now_ms / 1000,
+                    Some(file.parent),
+                )
+            }) {
+                Ok(ctx) => ctx,
+                Err((_, err)) => {
+                    return Err(err);
+                }
+            };
+
+            if !permission::check_file_read(&ctx.ps, &canister, id, file.parent) {
+                Err("permission denied".to_string())?;
+            }
+
+            Ok(store::analytics::get_daily_stats(id, from_day, to_day))
+        }
+    }
+}
+
+#[ic_cdk::query]
+fn get_files_stats_summary(
+    ids: Vec<u32>,
+    access_token: Option<ByteBuf>,
+) -> Result<Vec<FileStatsSummary>, String> {
+    if ids.len() > 100 {
+        Err("ids length should be less than or equal to 100".to_string())?;
+    }
+
+    let canister = ic_cdk::id();
+    let caller = ic_cdk::caller();
+    let now_sec = ic_cdk::api::time() / SECONDS;
+    let mut ctxs: BTreeMap<u32, store::Context> = BTreeMap::new();
+    let mut res = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Some(file) = store::fs::get_file(id) {
+            if !ctxs.contains_key(&file.parent) {
+                let ctx = store::state::with(|s| {
+                    s.read_permission(
+                        caller,
+                        &canister,
+                        access_token.clone(),
+                        now_sec,
+                        Some(file.parent),
+                    )
+                })
+                .map_err(|(_, err)| err)?;
+                ctxs.insert(file.parent, ctx);
+            }
+            let ctx = &ctxs[&file.parent];
+            if !permission::check_file_read(&ctx.ps, &canister, id, file.parent) {
+                Err("permission denied".to_string())?;
+            }
+            res.push(store::analytics::get_summary(id));
+        }
+    }
+    Ok(res)
+}
@@ -2,12 +2,31 @@ use canistore_types::{crc32, file::*, folder::*, to_cbor_bytes};
 use serde_bytes::ByteBuf;
 use std::collections::BTreeSet;
 
-use crate::{permission, store, MILLISECONDS, SECONDS};
+use crate::{guards::reporter_guard, permission, store, MILLISECONDS, SECONDS};
 
 #[ic_cdk::update]
 fn create_file(
     input: CreateFileInput,
     access_token: Option<ByteBuf>,
+) -> Result<CreateFileOutput, String> {
+    let now_ms = ic_cdk::api::time() / MILLISECONDS;
+    let canister = ic_cdk::id();
+    let ctx = match store::state::with(|s| {
+        s.write_permission(ic_cdk::caller(), &canister, access_token, now_ms / 1000)
+    }) {
+        Ok(ctx) => ctx,
+        Err((_, err)) => {
+            return Err(err);
+        }
+    };
+
+    create_file_with(&ctx, input, now_ms)
+}
+
+pub fn create_file_with(
+    ctx: &store::Context,
+    input: CreateFileInput,
+    now_ms: u64,
 ) -> Result<CreateFileOutput, String> {
     input.validate()?;
 
@@ -28,17 +47,7 @@ fn create_file(
         Ok(())
     })?;
 
-    let now_ms = ic_cdk::api::time() / MILLISECONDS;
     let canister = ic_cdk::id();
-    let ctx = match store::state::with(|s| {
-        s.write_permission(ic_cdk::caller(), &canister, access_token, now_ms / 1000)
-    }) {
-        Ok(ctx) => ctx,
-        Err((_, err)) => {
-            return Err(err);
-        }
-    };
-
     if !permission::check_file_create(&ctx.ps, &canister, input.parent) {
         Err("permission denied".to_string())?;
     }
@@ -145,12 +154,6 @@ fn update_file_chunk(
     input: UpdateFileChunkInput,
     access_token: Option<ByteBuf>,
 ) -> Result<UpdateFileChunkOutput, String> {
-    if let Some(checksum) = input.crc32 {
-        if crc32(&input.content) != checksum {
-            Err("crc32 checksum mismatch".to_string())?;
-        }
-    }
-
     let now_ms = ic_cdk::api::time() / MILLISECONDS;
     let canister = ic_cdk::id();
     let ctx = match store::state::with(|s| {
@@ -167,6 +170,21 @@ fn update_file_chunk(
         }
     };
 
+    update_file_chunk_with(&ctx, input, now_ms)
+}
+
+pub fn update_file_chunk_with(
+    ctx: &store::Context,
+    input: UpdateFileChunkInput,
+    now_ms: u64,
+) -> Result<UpdateFileChunkOutput, String> {
+    if let Some(checksum) = input.crc32 {
+        if crc32(&input.content) != checksum {
+            Err("crc32 checksum mismatch".to_string())?;
+        }
+    }
+
+    let canister = ic_cdk::id();
     let id = input.id;
     let filled = store::fs::update_chunk(
         input.id,
@@ -379,3 +397,10 @@ fn delete_folder(id: u32, access_token: Option<ByteBuf>) -> Result<bool, String>
         }
     })
 }
+
+#[ic_cdk::update(guard = "reporter_guard")]
+fn report_file_stats(events: Vec<FileStatEvent>) -> Result<u32, String> {
+    validate_file_stat_events(&events)?;
+    let now_ms = ic_cdk::api::time() / MILLISECONDS;
+    Ok(store::analytics::record(events, now_ms))
+}
@@ -25,3 +25,19 @@ pub fn admin_guard() -> Result<(), String> {
         Err("Error: Only the owner can call this action.".to_string())
     }
 }
+
+#[inline(always)]
+pub fn reporter_guard() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    let allowed = state::with(|state| {
+        (state.manager_canister != Principal::anonymous() && caller == state.manager_canister)
+            || state.managers.contains(&caller)
+            || state.reporters.contains(&caller)
+    });
+
+    if allowed || ic_cdk::api::is_controller(&caller) {
+        Ok(())
+    } else {
+        Err("Error: Only the trusted reporters can call this action.".to_string())
+    }
+}
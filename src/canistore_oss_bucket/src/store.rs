@@ -1,8 +1,10 @@
 use candid::Principal;
-use canistore_certificate::cose::{Token, BUCKET_TOKEN_AAD};
 use canistore_types::{
+    bucket::{SnapshotCursor, SnapshotPage, SNAPSHOT_VERSION},
+    cose::{Token, BUCKET_TOKEN_AAD},
     file::{
-        FileChunk, FileInfo, UpdateFileInput, CHUNK_SIZE, MAX_FILE_SIZE, MAX_FILE_SIZE_PER_CALL,
+        FileChunk, FileDailyStat, FileInfo, FileStatEvent, FileStatsSummary, UpdateFileInput,
+        CHUNK_SIZE, MAX_FILE_SIZE, MAX_FILE_SIZE_PER_CALL,
     },
     folder::{FolderInfo, FolderName, UpdateFolderInput},
     oss_permission::Policies,
@@ -53,6 +55,18 @@ pub struct Bucket {
     pub trusted_ecdsa_pub_keys: Vec<ByteBuf>,
     // used to verify the request token signed with ED25519
     pub trusted_eddsa_pub_keys: Vec<ByteN<32>>,
+    // trusted frontends or platform canisters that can report file analytics
+    #[serde(default)]
+    pub reporters: BTreeSet<Principal>,
+    // origins allowed by the HTTP gateway CORS policy, "*" allows any origin
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    // request headers allowed by CORS preflights, the default headers are used when empty
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    // response headers exposed to CORS requests, the default headers are used when empty
+    #[serde(default)]
+    pub exposed_headers: Vec<String>,
 }
 
 impl Default for Bucket {
@@ -73,6 +87,10 @@ impl Default for Bucket {
             auditors: BTreeSet::new(),
             trusted_ecdsa_pub_keys: Vec::new(),
             trusted_eddsa_pub_keys: Vec::new(),
+            reporters: BTreeSet::new(),
+            allowed_origins: Vec::new(),
+            allowed_headers: Vec::new(),
+            exposed_headers: Vec::new(),
         }
     }
 }
@@ -92,12 +110,14 @@ pub enum Role {
 }
 
 impl Bucket {
+    // folder: the folder that the resource belongs to, its visibility overrides the bucket's.
     pub fn read_permission(
         &self,
         caller: Principal,
         canister: &Principal,
         sign1_token: Option<ByteBuf>,
         now_sec: u64,
+        folder: Option<u32>,
     ) -> Result<Context, (u16, String)> {
         let mut ctx = Context {
             caller,
@@ -119,7 +139,10 @@ impl Bucket {
             Err((403, "bucket is archived".to_string()))?;
         }
 
-        if self.visibility > 0 || ctx.role >= Role::Auditor {
+        let visibility = folder
+            .and_then(|id| FOLDERS.with(|r| r.borrow().visibility(id)))
+            .unwrap_or(self.visibility);
+        if visibility > 0 || ctx.role >= Role::Auditor {
             return Ok(ctx);
         }
 
@@ -294,6 +317,48 @@ impl Storable for Chunk {
     }
 }
 
+// FileDay: (file id, days since unix epoch)
+// the day TOTAL_DAY holds the all-time total of the file.
+#[derive(Clone, Default, Deserialize, Serialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct FileDay(pub u32, pub u32);
+impl Storable for FileDay {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 11,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let mut buf = vec![];
+        into_writer(self, &mut buf).expect("failed to encode FileDay data");
+        Cow::Owned(buf)
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        from_reader(&bytes[..]).expect("failed to decode FileDay data")
+    }
+}
+
+// FileStat: (plays, downloads)
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct FileStat(pub u64, pub u64);
+
+impl Storable for FileStat {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 19,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let mut buf = vec![];
+        into_writer(self, &mut buf).expect("failed to encode FileStat data");
+        Cow::Owned(buf)
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        from_reader(&bytes[..]).expect("failed to decode FileStat data")
+    }
+}
+
 // folder
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct FolderMetadata {
@@ -304,6 +369,8 @@ pub struct FolderMetadata {
     pub created_at: u64,        // unix timestamp in milliseconds
     pub updated_at: u64,        // unix timestamp in milliseconds
     pub status: i8,             // -1: archived; 0: readable and writable; 1: readonly
+    #[serde(default)]
+    pub visibility: Option<u8>, // 0: private; 1: public; None: inherit from the parent folder
 }
 
 impl FolderMetadata {
@@ -317,6 +384,7 @@ impl FolderMetadata {
             status: self.status,
             files: self.files,
             folders: self.folders,
+            visibility: self.visibility,
         }
     }
 }
@@ -384,6 +452,27 @@ impl FoldersTree {
         depth
     }
 
+    // returns the visibility of the nearest folder that has set it.
+    fn visibility(&self, mut id: u32) -> Option<u8> {
+        let mut depth = 0;
+        while depth < 1024 {
+            match self.get(&id) {
+                None => break,
+                Some(folder) => {
+                    if folder.visibility.is_some() {
+                        return folder.visibility;
+                    }
+                    if id == 0 {
+                        break;
+                    }
+                    id = folder.parent;
+                    depth += 1;
+                }
+            }
+        }
+        None
+    }
+
     fn depth_or_is_ancestor(&self, mut id: u32, parent: u32) -> (usize, bool) {
         let mut depth = 0;
         while id != 0 && depth < 1024 {
@@ -710,6 +799,7 @@ const HASH_INDEX_MEMORY_ID: MemoryId = MemoryId::new(1);
 const FOLDERS_MEMORY_ID: MemoryId = MemoryId::new(2);
 const FS_METADATA_MEMORY_ID: MemoryId = MemoryId::new(3);
 const FS_CHUNKS_MEMORY_ID: MemoryId = MemoryId::new(4);
+const FS_STATS_MEMORY_ID: MemoryId = MemoryId::new(5);
 
 thread_local! {
     static HTTP_TREE: RefCell<HttpCertificationTree> = RefCell::new(HttpCertificationTree::default());
@@ -752,6 +842,12 @@ thread_local! {
             MEMORY_MANAGER.with_borrow(|m| m.get(FS_CHUNKS_MEMORY_ID)),
         )
     );
+
+    static FS_STATS_STORE: RefCell<StableBTreeMap<FileDay, FileStat, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(FS_STATS_MEMORY_ID)),
+        )
+    );
 }
 
 pub mod state {
@@ -1044,6 +1140,13 @@ pub mod fs {
                     if let Some(name) = change.name {
                         folder.name = name;
                     }
+                    if let Some(visibility) = change.visibility {
+                        folder.visibility = if visibility > 1 {
+                            None
+                        } else {
+                            Some(visibility)
+                        };
+                    }
                     folder.status = status;
                     folder.updated_at = now_ms;
                     Ok(())
@@ -1293,6 +1396,7 @@ pub mod fs {
                             fs_data.remove(&FileId(id, i));
                         }
                     });
+                    analytics::delete_file_stats(id);
                     Ok(true)
                 }
                 None => Ok(false),
@@ -1329,6 +1433,7 @@ pub mod fs {
                                         for i in 0..file.chunks {
                                             fs_data.remove(&FileId(id, i));
                                         }
+                                        analytics::delete_file_stats(id);
                                     }
                                 }
                                 None => {
@@ -1348,6 +1453,298 @@ pub mod fs {
     }
 }
 
+pub mod analytics {
+    use super::*;
+
+    pub const TOTAL_DAY: u32 = u32::MAX;
+    const DAY_MS: u64 = 24 * 3600 * 1000;
+
+    pub fn day_of(timestamp_ms: u64) -> u32 {
+        (timestamp_ms / DAY_MS) as u32
+    }
+
+    // aggregates the events into the daily and all-time counters,
+    // events of unknown files or without any counts are ignored.
+    pub fn record(events: Vec<FileStatEvent>, now_ms: u64) -> u32 {
+        FS_STATS_STORE.with(|r| {
+            let mut m = r.borrow_mut();
+            let mut accepted = 0u32;
+            for event in events {
+                if event.plays == 0 && event.downloads == 0 {
+                    continue;
+                }
+                if !FS_METADATA_STORE.with(|r| r.borrow().contains_key(&event.id)) {
+                    continue;
+                }
+
+                let day = day_of(event.timestamp.unwrap_or(now_ms).min(now_ms));
+                for key in [FileDay(event.id, day), FileDay(event.id, TOTAL_DAY)] {
+                    let mut stat = m.get(&key).unwrap_or_default();
+                    stat.0 = stat.0.saturating_add(event.plays as u64);
+                    stat.1 = stat.1.saturating_add(event.downloads as u64);
+                    m.insert(key, stat);
+                }
+                accepted += 1;
+            }
+            accepted
+        })
+    }
+
+    pub fn get_daily_stats(id: u32, from_day: u32, to_day: u32) -> Vec<FileDailyStat> {
+        FS_STATS_STORE.with(|r| {
+            r.borrow()
+                .range((
+                    ops::Bound::Included(FileDay(id, from_day)),
+                    ops::Bound::Included(FileDay(id, to_day.min(TOTAL_DAY - 1))),
+                ))
+                .map(
+                    |(FileDay(_, day), FileStat(plays, downloads))| FileDailyStat {
+                        day,
+                        plays,
+                        downloads,
+                    },
+                )
+                .collect()
+        })
+    }
+
+    pub fn get_summary(id: u32) -> FileStatsSummary {
+        let FileStat(plays, downloads) =
+            FS_STATS_STORE.with(|r| r.borrow().get(&FileDay(id, TOTAL_DAY)).unwrap_or_default());
+        FileStatsSummary {
+            id,
+            plays,
+            downloads,
+        }
+    }
+
+    pub fn delete_file_stats(id: u32) {
+        FS_STATS_STORE.with(|r| {
+            let mut m = r.borrow_mut();
+            let keys: Vec<FileDay> = m
+                .range((
+                    ops::Bound::Included(FileDay(id, 0)),
+                    ops::Bound::Included(FileDay(id, TOTAL_DAY)),
+                ))
+                .map(|(k, _)| k)
+                .collect();
+            for k in keys {
+                m.remove(&k);
+            }
+        });
+    }
+}
+
+pub mod snapshot {
+    use super::*;
+
+    const FILES_PER_PAGE: usize = 100;
+    // folder pages are limited by the number of folders and their children
+    const FOLDER_ENTRIES_PER_PAGE: usize = 50_000;
+    const HASHS_PER_PAGE: usize = 10_000;
+    const STATS_PER_PAGE: usize = 10_000;
+
+    #[derive(Deserialize, Serialize)]
+    enum Section {
+        Bucket(Bucket),
+        Folders(Vec<(u32, FolderMetadata)>),
+        HashIndex(Vec<(ByteArray<32>, u32)>),
+        Files(Vec<(u32, FileMetadata)>),
+        Chunks(Vec<(u32, u32, ByteBuf)>),
+        Stats(Vec<(u32, u32, u64, u64)>), // (file id, day, plays, downloads)
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct Snapshot {
+        version: u16,
+        section: Section,
+        // marks the final page, the importer refreshes the certified data after it
+        #[serde(default)]
+        last: bool,
+    }
+
+    pub fn export(cursor: SnapshotCursor) -> SnapshotPage {
+        let (section, next) = match cursor {
+            SnapshotCursor::Bucket => (
+                Section::Bucket(state::with(|s| s.clone())),
+                Some(SnapshotCursor::Folders(0)),
+            ),
+            SnapshotCursor::Folders(start) => {
+                let mut folders: Vec<(u32, FolderMetadata)> = Vec::new();
+                let mut next = SnapshotCursor::HashIndex(ByteN::default());
+                let mut filled = 0usize;
+                FOLDERS.with(|r| {
+                    for (id, folder) in r.borrow().range(start..) {
+                        filled += 1 + folder.files.len() + folder.folders.len();
+                        if !folders.is_empty() && filled > FOLDER_ENTRIES_PER_PAGE {
+                            next = SnapshotCursor::Folders(*id);
+                            break;
+                        }
+                        folders.push((*id, folder.clone()));
+                    }
+                });
+                (Section::Folders(folders), Some(next))
+            }
+            SnapshotCursor::HashIndex(start) => {
+                let mut hashs: Vec<(ByteArray<32>, u32)> = HASHS.with(|r| {
+                    r.borrow()
+                        .range(start.0..)
+                        .take(HASHS_PER_PAGE + 1)
+                        .map(|(hash, id)| (*hash, *id))
+                        .collect()
+                });
+                let next = if hashs.len() > HASHS_PER_PAGE {
+                    hashs
+                        .pop()
+                        .map(|(hash, _)| SnapshotCursor::HashIndex(hash.into()))
+                } else {
+                    Some(SnapshotCursor::Files(0))
+                };
+                (Section::HashIndex(hashs), next)
+            }
+            SnapshotCursor::Files(start) => {
+                let files: Vec<(u32, FileMetadata)> = FS_METADATA_STORE
+                    .with(|r| r.borrow().range(start..).take(FILES_PER_PAGE).collect());
+                let next = match files.last() {
+                    Some((id, _)) if files.len() == FILES_PER_PAGE => id
+                        .checked_add(1)
+                        .map(SnapshotCursor::Files)
+                        .unwrap_or(SnapshotCursor::Chunks(0, 0)),
+                    _ => SnapshotCursor::Chunks(0, 0),
+                };
+                (Section::Files(files), Some(next))
+            }
+            SnapshotCursor::Chunks(id, index) => {
+                let mut chunks: Vec<(u32, u32, ByteBuf)> = Vec::new();
+                let mut next: Option<SnapshotCursor> = None;
+                let mut filled = 0usize;
+                FS_CHUNKS_STORE.with(|r| {
+                    for (FileId(id, index), Chunk(chunk)) in r.borrow().range(FileId(id, index)..) {
+                        filled += chunk.len();
+                        if !chunks.is_empty() && filled > MAX_FILE_SIZE_PER_CALL as usize {
+                            next = Some(SnapshotCursor::Chunks(id, index));
+                            break;
+                        }
+                        chunks.push((id, index, ByteBuf::from(chunk)));
+                    }
+                });
+                (
+                    Section::Chunks(chunks),
+                    Some(next.unwrap_or(SnapshotCursor::Stats(0, 0))),
+                )
+            }
+            SnapshotCursor::Stats(id, day) => {
+                let mut stats: Vec<(u32, u32, u64, u64)> = FS_STATS_STORE.with(|r| {
+                    r.borrow()
+                        .range(FileDay(id, day)..)
+                        .take(STATS_PER_PAGE + 1)
+                        .map(|(FileDay(id, day), FileStat(plays, downloads))| {
+                            (id, day, plays, downloads)
+                        })
+                        .collect()
+                });
+                let next = if stats.len() > STATS_PER_PAGE {
+                    stats
+                        .pop()
+                        .map(|(id, day, _, _)| SnapshotCursor::Stats(id, day))
+                } else {
+                    None
+                };
+                (Section::Stats(stats), next)
+            }
+        };
+
+        let mut data = vec![];
+        into_writer(
+            &Snapshot {
+                version: SNAPSHOT_VERSION,
+                section,
+                last: next.is_none(),
+            },
+            &mut data,
+        )
+        .expect("failed to encode Snapshot data");
+        SnapshotPage {
+            data: ByteBuf::from(data),
+            next,
+        }
+    }
+
+    // imports a snapshot page, it overwrites the existing data so that it can be retried.
+    // the bucket page starts a new import and clears the folders, files, chunks and analytics
+    // left from previous data. the manager_canister of the current bucket is kept.
+    pub fn import(data: &[u8]) -> Result<(), String> {
+        let snapshot: Snapshot =
+            from_reader(data).map_err(|err| format!("invalid snapshot data: {:?}", err))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            Err(format!(
+                "unsupported snapshot version: {}",
+                snapshot.version
+            ))?;
+        }
+
+        match snapshot.section {
+            Section::Bucket(bucket) => {
+                state::with_mut(|s| {
+                    let manager_canister = s.manager_canister;
+                    *s = bucket;
+                    s.manager_canister = manager_canister;
+                });
+                FOLDERS.with(|r| *r.borrow_mut() = FoldersTree::new());
+                HASHS.with(|r| r.borrow_mut().clear());
+                FS_METADATA_STORE.with(|r| r.borrow_mut().clear_new());
+                FS_CHUNKS_STORE.with(|r| r.borrow_mut().clear_new());
+                FS_STATS_STORE.with(|r| r.borrow_mut().clear_new());
+            }
+            Section::Folders(folders) => FOLDERS.with(|r| {
+                let mut m = r.borrow_mut();
+                for (id, folder) in folders {
+                    m.insert(id, folder);
+                }
+            }),
+            Section::HashIndex(hashs) => HASHS.with(|r| {
+                let mut m = r.borrow_mut();
+                for (hash, id) in hashs {
+                    m.insert(hash, id);
+                }
+            }),
+            Section::Files(files) => FS_METADATA_STORE.with(|r| {
+                let mut m = r.borrow_mut();
+                for (id, file) in files {
+                    m.insert(id, file);
+                }
+            }),
+            Section::Chunks(chunks) => {
+                for (id, _, chunk) in &chunks {
+                    if chunk.len() > CHUNK_SIZE as usize {
+                        Err(format!("chunk size exceeds the limit {}", CHUNK_SIZE))?;
+                    }
+                    if !FS_METADATA_STORE.with(|r| r.borrow().contains_key(id)) {
+                        Err(format!("file not found: {}", id))?;
+                    }
+                }
+                FS_CHUNKS_STORE.with(|r| {
+                    let mut m = r.borrow_mut();
+                    for (id, index, chunk) in chunks {
+                        m.insert(FileId(id, index), Chunk(chunk.into_vec()));
+                    }
+                });
+            }
+            Section::Stats(stats) => FS_STATS_STORE.with(|r| {
+                let mut m = r.borrow_mut();
+                for (id, day, plays, downloads) in stats {
+                    m.insert(FileDay(id, day), FileStat(plays, downloads));
+                }
+            }),
+        }
+
+        if snapshot.last {
+            state::init_http_certified_data();
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1363,6 +1760,206 @@ mod test {
         println!("FileId min_size: {:?}, {}", v.len(), hex::encode(&v));
     }
 
+    #[test]
+    fn test_analytics() {
+        let f1 = fs::add_file(FileMetadata {
+            name: "stats.mp3".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let day = 20000u32;
+        let now_ms = (day as u64 + 1) * 24 * 3600 * 1000 + 1;
+
+        let accepted = analytics::record(
+            vec![
+                FileStatEvent {
+                    id: f1,
+                    plays: 2,
+                    downloads: 1,
+                    timestamp: Some(day as u64 * 24 * 3600 * 1000),
+                },
+                FileStatEvent {
+                    id: f1,
+                    plays: 3,
+                    downloads: 0,
+                    timestamp: None,
+                },
+                FileStatEvent {
+                    id: f1,
+                    plays: 0,
+                    downloads: 0,
+                    timestamp: None,
+                },
+                FileStatEvent {
+                    id: f1 + 100,
+                    plays: 1,
+                    downloads: 1,
+                    timestamp: None,
+                },
+            ],
+            now_ms,
+        );
+        assert_eq!(accepted, 2);
+
+        let stats = analytics::get_daily_stats(f1, 0, analytics::TOTAL_DAY);
+        assert_eq!(
+            stats,
+            vec![
+                FileDailyStat {
+                    day,
+                    plays: 2,
+                    downloads: 1
+                },
+                FileDailyStat {
+                    day: day + 1,
+                    plays: 3,
+                    downloads: 0
+                }
+            ]
+        );
+        let summary = analytics::get_summary(f1);
+        assert_eq!(summary.plays, 5);
+        assert_eq!(summary.downloads, 1);
+
+        assert!(fs::delete_file(f1, 0, |_| Ok(())).unwrap());
+        assert!(analytics::get_daily_stats(f1, 0, analytics::TOTAL_DAY).is_empty());
+        assert_eq!(analytics::get_summary(f1).plays, 0);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        state::with_mut(|b| {
+            b.enable_hash_index = true;
+            b.name = "snapshot".to_string();
+        });
+        let folder = fs::add_folder(FolderMetadata {
+            name: "albums".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let f1 = fs::add_file(FileMetadata {
+            parent: folder,
+            name: "f1.mp3".to_string(),
+            hash: Some(ByteN::from([1u8; 32])),
+            ..Default::default()
+        })
+        .unwrap();
+        let _ = fs::update_chunk(f1, 0, 999, [1u8; 32].to_vec(), |_| Ok(())).unwrap();
+        let _ = fs::update_chunk(f1, 1, 1000, [2u8; 32].to_vec(), |_| Ok(())).unwrap();
+
+        let mut pages: Vec<ByteBuf> = Vec::new();
+        let mut cursor = Some(SnapshotCursor::Bucket);
+        while let Some(c) = cursor {
+            let page = snapshot::export(c);
+            pages.push(page.data);
+            cursor = page.next;
+        }
+        assert_eq!(pages.len(), 5);
+
+        assert!(fs::delete_file(f1, 0, |_| Ok(())).unwrap());
+        state::with_mut(|b| {
+            b.name = "default".to_string();
+        });
+        assert!(fs::get_file(f1).is_none());
+
+        // import twice to make sure it is idempotent
+        for _ in 0..2 {
+            for page in &pages {
+                snapshot::import(page).unwrap();
+            }
+        }
+        assert_eq!(state::with(|b| b.name.clone()), "snapshot");
+        assert_eq!(fs::get_file(f1).unwrap().name, "f1.mp3");
+        assert_eq!(fs::get_file_id(&[1u8; 32]), Some(f1));
+        assert!(fs::get_folder(folder).unwrap().files.contains(&f1));
+        let mut data = [1u8; 32].to_vec();
+        data.extend_from_slice(&[2u8; 32]);
+        assert_eq!(fs::get_full_chunks(f1).unwrap(), data);
+
+        assert!(snapshot::import(&[0u8; 4]).is_err());
+    }
+
+    #[test]
+    fn test_folder_visibility() {
+        let canister = Principal::management_canister();
+        let caller = Principal::anonymous();
+        let covers = fs::add_folder(FolderMetadata {
+            name: "covers".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let thumbs = fs::add_folder(FolderMetadata {
+            parent: covers,
+            name: "thumbs".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let masters = fs::add_folder(FolderMetadata {
+            name: "masters".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        // private bucket
+        assert!(
+            state::with(|s| s.read_permission(caller, &canister, None, 0, Some(thumbs))).is_err()
+        );
+        fs::update_folder(
+            UpdateFolderInput {
+                id: covers,
+                visibility: Some(1),
+                ..Default::default()
+            },
+            0,
+            |_| Ok(()),
+        )
+        .unwrap();
+        assert_eq!(fs::get_folder(covers).unwrap().visibility, Some(1));
+        assert!(
+            state::with(|s| s.read_permission(caller, &canister, None, 0, Some(covers))).is_ok()
+        );
+        assert!(
+            state::with(|s| s.read_permission(caller, &canister, None, 0, Some(thumbs))).is_ok()
+        );
+        assert!(
+            state::with(|s| s.read_permission(caller, &canister, None, 0, Some(masters))).is_err()
+        );
+        assert!(state::with(|s| s.read_permission(caller, &canister, None, 0, None)).is_err());
+
+        // public bucket
+        state::with_mut(|s| s.visibility = 1);
+        fs::update_folder(
+            UpdateFolderInput {
+                id: masters,
+                visibility: Some(0),
+                ..Default::default()
+            },
+            0,
+            |_| Ok(()),
+        )
+        .unwrap();
+        assert!(
+            state::with(|s| s.read_permission(caller, &canister, None, 0, Some(masters))).is_err()
+        );
+        assert!(state::with(|s| s.read_permission(caller, &canister, None, 0, Some(0))).is_ok());
+
+        // reset to inherit
+        fs::update_folder(
+            UpdateFolderInput {
+                id: masters,
+                visibility: Some(2),
+                ..Default::default()
+            },
+            0,
+            |_| Ok(()),
+        )
+        .unwrap();
+        assert_eq!(fs::get_folder(masters).unwrap().visibility, None);
+        assert!(
+            state::with(|s| s.read_permission(caller, &canister, None, 0, Some(masters))).is_ok()
+        );
+    }
+
     #[test]
     fn test_role() {
         assert!(Role::Manager > Role::Auditor);
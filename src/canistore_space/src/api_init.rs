@@ -22,6 +22,11 @@ fn init(args: Option<CanisterArgs>) {
                 space.env = args.env;
             });
             store::state::save();
+            store::license::set_license_expiry_timer();
+            store::payment::set_stale_order_sweep_timer();
+            store::treasury::set_treasury_sweep_timer();
+            store::state::set_token_fee_refresh_timer();
+            store::release::schedule_next_release();
         }
         Some(CanisterArgs::Upgrade(_)) => {
             ic_cdk::trap(
@@ -81,4 +86,12 @@ fn post_upgrade(args: Option<CanisterArgs>) {
         }
         _ => {}
     }
+
+    store::role_audit::migrate_legacy_managers();
+    store::follower::migrate_legacy_followers();
+    store::license::set_license_expiry_timer();
+    store::payment::set_stale_order_sweep_timer();
+    store::treasury::set_treasury_sweep_timer();
+    store::state::set_token_fee_refresh_timer();
+    store::release::schedule_next_release();
 }
@@ -1,18 +1,28 @@
 use candid::Principal;
 use canistore_types::error::{CustomError, ErrorCode};
 use canistore_types::license::{
-    LicenseListEntry, LicenseSource, LicenseTrackListEntry, QueryLicenseResp,
+    LicenseKey, LicenseListEntry, LicenseSource, LicenseTrackListEntry, LicenseUsageReport,
+    QueryLicenseRecordResp, QueryLicenseResp, TrackUsageReport,
+};
+use canistore_types::payment::{
+    AwardSupporter, JournalBalance, PayoutRecord, QueryAwardReq, QueryAwardResp, QueryCommonReq,
+    QueryCommonSubscriber, QueryJournalReq, QueryJournalResp, QueryOrderResp, QueryPayoutResp,
+    QuerySelfSubscriber, TokenInfo,
 };
-use canistore_types::payment::{QueryCommonReq, QueryOrderResp};
 use canistore_types::space::{
-    Album, AlbumListEntry, QueryTrackResp, SharedTrackListResp, Track, UserPost,
+    Album, AlbumListEntry, ManagerRole, QueryAlbumReq, QueryAlbumResp, QueryBlackUserResp,
+    QueryCategory, QueryCommentReq, QueryCommentResp, QueryFollowerResp, QueryManager,
+    QueryRoleAuditResp, QueryTrackResp, SharedTrackListResp, Track, UserPost,
 };
-use ic_cdk::caller;
+use ic_cdk::{api::time, caller};
 use ic_ledger_types::AccountIdentifier;
 
-use crate::guards::{anonymous_guard, controller_guard};
-use crate::store::{self, state, SpaceInfo};
-use crate::utils::account_id;
+use crate::guards::{
+    anonymous_guard, controller_guard, finance_guard, moderator_guard, owner_guard, read_guard,
+    write_guard,
+};
+use crate::store::{self, album::AlbumAccess, state, SpaceInfo};
+use crate::utils::{account_id, check_page_size, month_range, parse_album_id};
 
 #[ic_cdk::query]
 fn api_version() -> u16 {
@@ -29,12 +39,52 @@ fn get_space_info() -> Result<SpaceInfo, String> {
     }))
 }
 
-#[ic_cdk::query]
+fn is_manager(caller: Principal) -> bool {
+    state::with(|space| space.write_permission(caller).is_ok())
+}
+
+// Comments, awards and licenses follow the access of their album.
+fn album_visible(album_id: u64, caller: Principal, is_manager: bool) -> bool {
+    store::album::get_album(album_id).is_some_and(|album| {
+        store::album::album_access(&album.into_inner(), caller, is_manager) != AlbumAccess::Hidden
+    })
+}
+
+// Track licenses follow the album of the track, tracks outside an album are only visible to managers.
+fn license_visible(key: &LicenseKey, caller: Principal, is_manager: bool) -> bool {
+    if is_manager {
+        return true;
+    }
+    key.album_id
+        .filter(|id| *id > 0)
+        .or_else(|| {
+            key.track_id
+                .and_then(store::track::get_track)
+                .and_then(|track| track.into_inner().album_id)
+        })
+        .is_some_and(|album_id| album_visible(album_id, caller, is_manager))
+}
+
+fn award_visible(album_id: &str, caller: Principal, is_manager: bool) -> bool {
+    album_id
+        .parse::<u64>()
+        .is_ok_and(|album_id| album_visible(album_id, caller, is_manager))
+}
+
+// Hidden albums are reported as missing, so drafts do not leak their existence.
+#[ic_cdk::query(guard = "read_guard")]
 fn get_album_info(id: u64) -> Result<Album, String> {
-    let album = store::album::get_album(id);
+    let caller = caller();
+    let is_manager = is_manager(caller);
+    let album = store::album::get_album(id)
+        .map(|album| album.into_inner())
+        .filter(|album| {
+            store::album::album_access(album, caller, is_manager) != AlbumAccess::Hidden
+        });
     match album {
         None => Err("album not found".to_string()),
-        Some(album) => Ok(album.into_inner()),
+        Some(album) if is_manager => Ok(album),
+        Some(album) => Ok(album.public_view()),
     }
 }
 
@@ -50,39 +100,85 @@ fn get_album_info(id: u64) -> Result<Album, String> {
 //     }
 // }
 
-#[ic_cdk::query]
+#[ic_cdk::query(guard = "read_guard")]
 fn get_albums_list(limit: usize, offset: usize) -> Vec<AlbumListEntry> {
-    store::album::get_albums_list(limit, offset)
+    store::album::get_albums_list(limit, offset, is_manager(caller()))
+}
+
+#[ic_cdk::query(guard = "read_guard")]
+fn query_albums(req: QueryAlbumReq) -> QueryAlbumResp {
+    let (total, has_more, stat, data) = store::album::query_albums(&req, is_manager(caller()));
+
+    QueryAlbumResp {
+        page: req.page,
+        total: total as u32,
+        has_more,
+        stat,
+        data,
+    }
 }
 
 #[ic_cdk::query]
+fn get_categories() -> Vec<QueryCategory> {
+    store::category::get_category_tree()
+}
+
+#[ic_cdk::query(guard = "read_guard")]
 fn get_license_by_track(track_id: u64, user_pid: Principal) -> Option<QueryLicenseResp> {
-    match store::license::get_license_by_track(track_id) {
-        Some(license_map) => {
-            if let Some(license) = license_map.get(&user_pid).cloned() {
-                if let Some(track) = store::track::get_track(track_id) {
-                    let source = LicenseSource::Track(track.into_inner());
-                    Some(QueryLicenseResp::new(license, source))
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        }
-        None => None,
+    let caller = caller();
+    let key = LicenseKey::new(None, Some(track_id));
+    if !license_visible(&key, caller, is_manager(caller)) {
+        return None;
     }
+    let (license, by_album) = store::license::find_track_license(track_id, &user_pid)?;
+    let source = if by_album {
+        let (_, album) = store::track::get_track_and_album(track_id).ok()?;
+        LicenseSource::Album(album)
+    } else {
+        LicenseSource::Track(store::track::get_track(track_id)?.into_inner())
+    };
+    Some(QueryLicenseResp::new(license, source))
 }
 
-#[ic_cdk::query]
+#[ic_cdk::query(guard = "read_guard")]
+fn get_license_by_album(album_id: u64, user_pid: Principal) -> Option<QueryLicenseResp> {
+    let caller = caller();
+    if !album_visible(album_id, caller, is_manager(caller)) {
+        return None;
+    }
+    let license = store::license::get_license_by_album(album_id)?
+        .get(&user_pid)
+        .cloned()?;
+    let album = store::album::get_album(album_id)?.into_inner();
+    Some(QueryLicenseResp::new(license, LicenseSource::Album(album)))
+}
+
+#[ic_cdk::query(guard = "read_guard")]
+fn get_album_license_list(limit: usize, offset: usize) -> Vec<LicenseListEntry> {
+    let caller = caller();
+    let is_manager = is_manager(caller);
+    store::license::get_album_license_list(limit, offset, &|key| {
+        license_visible(key, caller, is_manager)
+    })
+}
+
+#[ic_cdk::query(guard = "read_guard")]
 fn get_license_list(limit: usize, offset: usize) -> Vec<LicenseListEntry> {
-    store::license::get_all_licenses(limit, offset)
+    let caller = caller();
+    let is_manager = is_manager(caller);
+    store::license::get_all_licenses(limit, offset, &|key| {
+        license_visible(key, caller, is_manager)
+    })
 }
 
-#[ic_cdk::query]
+#[ic_cdk::query(guard = "read_guard")]
 fn get_track_license_list(limit: usize, offset: usize) -> Vec<LicenseTrackListEntry> {
+    let caller = caller();
+    let is_manager = is_manager(caller);
     let license_entries: Vec<LicenseListEntry> =
-        store::license::get_track_license_list(limit, offset);
+        store::license::get_track_license_list(limit, offset, &|key| {
+            license_visible(key, caller, is_manager)
+        });
 
     license_entries
         .into_iter()
@@ -96,12 +192,35 @@ fn get_track_license_list(limit: usize, offset: usize) -> Vec<LicenseTrackListEn
         .collect()
 }
 
-#[ic_cdk::query]
+// Tracks follow the access of their album, tracks outside an album are visible once public.
+#[ic_cdk::query(guard = "read_guard")]
 fn get_track_info(id: u64) -> Result<Track, String> {
-    let track = store::track::get_track(id);
-    match track {
-        None => Err(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string()),
-        Some(track) => Ok(track.into_inner()),
+    let caller = caller();
+    let is_manager = is_manager(caller);
+    let track = store::track::get_track(id)
+        .map(|track| track.into_inner())
+        .ok_or_else(|| CustomError::new(ErrorCode::NoDataFound, Some("Track")).to_string())?;
+    if is_manager {
+        return Ok(track);
+    }
+
+    let access = match track.album_id {
+        Some(album_id) => store::album::get_album(album_id).map_or(AlbumAccess::Hidden, |album| {
+            store::album::album_access(&album.into_inner(), caller, is_manager)
+        }),
+        None if track.public_at > 0 => AlbumAccess::Full,
+        None => AlbumAccess::Hidden,
+    };
+    match access {
+        AlbumAccess::Full => Ok(track.public_view()),
+        AlbumAccess::Metadata => Err(CustomError::new(
+            ErrorCode::OperationNotAllowed,
+            Some("Subscription required"),
+        )
+        .to_string()),
+        AlbumAccess::Hidden => {
+            Err(CustomError::new(ErrorCode::NoDataFound, Some("Track")).to_string())
+        }
     }
 }
 
@@ -140,7 +259,7 @@ pub fn query_orders(req: QueryCommonReq) -> QueryOrderResp {
     let caller = caller(); // Get the caller's principal
 
     // Call limit_orders function
-    let (total, has_more, data) = store::payment::limit_orders(caller, &req);
+    let (total, has_more, data) = store::payment::limit_orders(Some(caller), &req);
 
     // Return the response
     QueryOrderResp {
@@ -151,28 +270,60 @@ pub fn query_orders(req: QueryCommonReq) -> QueryOrderResp {
     }
 }
 
-#[ic_cdk::query]
+#[ic_cdk::query(guard = "finance_guard")]
+fn query_space_orders(req: QueryCommonReq) -> QueryOrderResp {
+    let (total, has_more, data) = store::payment::limit_orders(None, &req);
+
+    QueryOrderResp {
+        page: req.page,
+        total,
+        has_more,
+        data,
+    }
+}
+
+#[ic_cdk::query(guard = "read_guard")]
 fn get_album_tracks_list(id: u64) -> Result<Vec<QueryTrackResp>, String> {
     let album = store::album::get_album(id);
 
     match album {
         None => Err(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string()),
         Some(album) => {
-            let tracks = store::track::get_tracks_by_ids(album.into_inner().track_ids);
+            let album = album.into_inner();
+
+            // Subscription albums are only listed for managers and active subscribers.
+            match store::album::album_access(&album, caller(), is_manager(caller())) {
+                AlbumAccess::Full => {}
+                AlbumAccess::Metadata => {
+                    return Err(CustomError::new(
+                        ErrorCode::OperationNotAllowed,
+                        Some("Subscription required"),
+                    )
+                    .to_string());
+                }
+                AlbumAccess::Hidden => {
+                    return Err(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string());
+                }
+            }
+
+            let tracks = store::track::get_tracks_by_ids(album.track_ids);
 
             // Map over tracks and convert them to QueryTrackResp with license check
             let query_track_resps: Vec<QueryTrackResp> = tracks
                 .into_iter()
-                .map(|track| {
-                    let has_license = store::license::get_license_by_track(track.id).is_some();
+                .enumerate()
+                .map(|(index, track)| {
+                    let has_license = store::license::is_track_licensed(track.id);
                     let has_share = store::share::get_share(track.id).is_some();
                     let has_share_store = state::get_is_share_store(track.id);
-                    QueryTrackResp::from_with_license(
+                    let mut resp = QueryTrackResp::from_with_license(
                         track,
                         has_license,
                         has_share,
                         has_share_store,
-                    )
+                    );
+                    resp.track_number = index as u32 + 1;
+                    resp
                 })
                 .collect();
 
@@ -181,6 +332,180 @@ fn get_album_tracks_list(id: u64) -> Result<Vec<QueryTrackResp>, String> {
     }
 }
 
+#[ic_cdk::query(guard = "owner_guard")]
+fn get_followers(req: QueryCommonReq) -> QueryFollowerResp {
+    let (total, has_more, data) = store::follower::limit_followers(&req);
+
+    QueryFollowerResp {
+        page: req.page,
+        total,
+        has_more,
+        data,
+    }
+}
+
+#[ic_cdk::query]
+fn get_album_subscriber(album_id: u64) -> Result<QueryCommonSubscriber, String> {
+    if store::album::get_album(album_id).is_none() {
+        return Err(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string());
+    }
+
+    let data = store::album::get_subscriber(album_id, caller());
+    let is_subscriber = data
+        .as_ref()
+        .map_or(false, |subscriber| subscriber.is_active(time()));
+
+    Ok(QueryCommonSubscriber {
+        data,
+        is_subscriber,
+    })
+}
+
+#[ic_cdk::query(guard = "anonymous_guard")]
+fn get_self_subscriber(album_id: u64) -> Result<QuerySelfSubscriber, String> {
+    if store::album::get_album(album_id).is_none() {
+        return Err(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string());
+    }
+
+    let caller = caller();
+    Ok(QuerySelfSubscriber {
+        data: store::album::get_subscriber(album_id, caller),
+        is_blacklisted: state::with(|space| space.is_blacklisted(&caller)),
+    })
+}
+
+#[ic_cdk::query(guard = "moderator_guard")]
+fn get_blacklist(req: QueryCommonReq) -> QueryBlackUserResp {
+    let (total, has_more, data) = state::limit_blacklist(&req);
+
+    QueryBlackUserResp {
+        page: req.page as u32,
+        total: total as u32,
+        has_more,
+        data,
+    }
+}
+
+#[ic_cdk::query(guard = "write_guard")]
+fn get_managers() -> Vec<QueryManager> {
+    state::get_managers()
+}
+
+#[ic_cdk::query(guard = "owner_guard")]
+fn query_role_audit(req: QueryCommonReq) -> QueryRoleAuditResp {
+    let (total, has_more, data) = store::role_audit::limit_entries(&req);
+
+    QueryRoleAuditResp {
+        page: req.page as u32,
+        total: total as u32,
+        has_more,
+        data,
+    }
+}
+
+#[ic_cdk::query]
+fn get_tokens() -> Vec<TokenInfo> {
+    state::with(|space| space.tokens.clone())
+}
+
+#[ic_cdk::query(guard = "read_guard")]
+fn query_awards(req: QueryAwardReq) -> QueryAwardResp {
+    let caller = caller();
+    let is_manager = is_manager(caller);
+    let (total, has_more, data) = store::award::limit_awards(&req, &|album_id| {
+        award_visible(album_id, caller, is_manager)
+    });
+
+    QueryAwardResp {
+        page: req.page,
+        total: total as u32,
+        has_more,
+        data,
+    }
+}
+
+// Without an album_id the leaderboard covers every album of the space.
+#[ic_cdk::query(guard = "read_guard")]
+fn get_top_supporters(album_id: Option<String>, token: String, limit: u32) -> Vec<AwardSupporter> {
+    let caller = caller();
+    let is_manager = is_manager(caller);
+    let (_, limit) = check_page_size(1, limit as usize);
+    store::award::top_supporters(album_id.as_deref(), &token, limit, &|album_id| {
+        award_visible(album_id, caller, is_manager)
+    })
+}
+
+#[ic_cdk::query(guard = "finance_guard")]
+fn get_license_usage_report(license_id: u64) -> Result<LicenseUsageReport, String> {
+    let license = store::license::find_license(license_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("License")).to_string())?;
+    let records = store::license::get_license_records(license_id);
+    Ok(LicenseUsageReport::new(&license, &records))
+}
+
+#[ic_cdk::query(guard = "finance_guard")]
+fn get_track_usage_report(track_id: u64) -> Result<TrackUsageReport, String> {
+    if store::track::get_track(track_id).is_none() {
+        return Err(CustomError::new(ErrorCode::NoDataFound, Some("Track")).to_string());
+    }
+    Ok(store::license::get_track_usage_report(track_id))
+}
+
+#[ic_cdk::query(guard = "finance_guard")]
+fn query_license_records(license_id: u64, req: QueryCommonReq) -> QueryLicenseRecordResp {
+    let (total, has_more, data) = store::license::limit_license_records(license_id, &req);
+
+    QueryLicenseRecordResp {
+        page: req.page,
+        total,
+        has_more,
+        data,
+    }
+}
+
+#[ic_cdk::query(guard = "finance_guard")]
+fn get_order_payouts(order_id: u64) -> Vec<PayoutRecord> {
+    store::payout::get_order_payouts(order_id)
+}
+
+#[ic_cdk::query(guard = "anonymous_guard")]
+fn query_payouts(req: QueryCommonReq) -> QueryPayoutResp {
+    let (total, has_more, data) = store::payout::limit_payouts(caller(), &req);
+
+    QueryPayoutResp {
+        page: req.page,
+        total,
+        has_more,
+        data,
+    }
+}
+
+#[ic_cdk::query(guard = "finance_guard")]
+fn query_journal(req: QueryJournalReq) -> QueryJournalResp {
+    let (total, has_more, data) = store::journal::limit_entries(&req);
+
+    QueryJournalResp {
+        page: req.page,
+        total: total as u32,
+        has_more,
+        data,
+    }
+}
+
+// Debit and credit totals per token and account over [start, end), an end of 0 is unbounded
+#[ic_cdk::query(guard = "finance_guard")]
+fn get_journal_balances(token: Option<String>, start: u64, end: u64) -> Vec<JournalBalance> {
+    store::journal::balances(token.as_deref(), start, end)
+}
+
+// Journal entries of a calendar month (UTC) as CSV
+#[ic_cdk::query(guard = "finance_guard")]
+fn get_monthly_statement(year: u32, month: u32) -> Result<String, String> {
+    let (start, end) = month_range(year, month)
+        .ok_or_else(|| CustomError::new(ErrorCode::ParamsError, Some("month")).to_string())?;
+    Ok(store::journal::statement_csv(start, end))
+}
+
 #[ic_cdk::query]
 fn get_share_list(limit: usize, offset: usize) -> Vec<SharedTrackListResp> {
     store::share::get_share_list(limit, offset)
@@ -195,3 +520,27 @@ fn get_post_list(limit: usize, offset: usize) -> Vec<UserPost> {
 fn get_share_store_list(limit: usize, offset: usize) -> Vec<Track> {
     state::get_share_store_list(limit, offset)
 }
+
+#[ic_cdk::query(guard = "read_guard")]
+fn query_comments(req: QueryCommentReq) -> Result<QueryCommentResp, String> {
+    let album_id = parse_album_id(&req.album_id)?;
+    let caller = caller();
+    if !album_visible(album_id, caller, is_manager(caller)) {
+        return Err(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string());
+    }
+    let is_manager = state::with(|space| {
+        space
+            .role_permission(caller, ManagerRole::Moderator)
+            .is_ok()
+    });
+
+    let (total, has_more, data) =
+        store::comment::limit_comments(album_id, &req, caller, is_manager);
+
+    Ok(QueryCommentResp {
+        page: req.page,
+        total: total as u32,
+        has_more,
+        data,
+    })
+}
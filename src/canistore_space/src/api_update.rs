@@ -4,30 +4,44 @@ use canistore_types::{
     constant::CanisterType,
     dao::DaoStateInfo,
     error::{CustomError, ErrorCode},
-    license::CreateTrackLicenseArg,
-    message::{MessageSource, MessageType, MsgShareTrack, MsgUserPost},
-    payment::{LicensePrice, PaymentInfo, PaymentType, SPACE_LICENSE_PRICE_ICP},
+    license::{
+        CreateAlbumLicenseArg, CreateTrackLicenseArg, License, LicenseKey, LicenseRecord,
+        LicenseUsageArg, MAX_LICENSE_USAGE_EVENTS,
+    },
+    message::{MessageSource, MessageType, MsgFollowSpace, MsgShareTrack, MsgUserPost},
+    payment::{
+        validate_revenue_shares, AwardOrder, Icrc2Checkout, JournalAccount, JournalKind,
+        LicensePrice, PaymentInfo, PaymentType, RevenueShare, Subscriber, SubscriberInfo,
+        SubscriptionType, TokenInfo, TokenPrice, TreasuryBalance, SPACE_LICENSE_PRICE_ICP,
+        TREASURY_SUBACCOUNT,
+    },
     platform::TrackInfo,
     space::{
-        Album, CreateAlbumArg, CreateTrackArg, EditAlbumArg, EditTrackArg, SharedTrack, Track,
-        UserPost,
+        Album, AlbumStatus, CategoryArgs, Comment, CommentArgs, CommentStatus, CreateAlbumArg,
+        CreateTrackArg, EditAlbumArg, EditTrackArg, ManagerRole, QueryComment, SharedTrack, Track,
+        UserPost, MAX_COMMENT_LENGTH,
     },
     user::Attribute,
 };
 use ic_cdk::{api::time, caller};
-use ic_ledger_types::{
-    AccountBalanceArgs, Tokens, TransferArgs, TransferResult, MAINNET_LEDGER_CANISTER_ID,
-};
+use ic_ledger_types::{AccountBalanceArgs, Tokens, TransferArgs, TransferResult};
+use icrc_ledger_types::icrc1::account::Account;
+use std::collections::BTreeSet;
 
 use crate::{
     api_query::canister_account,
     canister_service::CanisterService,
-    guards::{anonymous_guard, owner_guard, write_guard},
-    store::{self, state},
+    guards::{
+        anonymous_guard, blacklist_guard, editor_guard, finance_guard, moderator_guard,
+        owner_guard, publisher_guard,
+    },
+    pay::{account_transfer, fetch_token_info, token_balance, token_fee, token_ledger},
+    store::{self, album::AlbumAccess, state},
+    utils::parse_album_id,
     SHARE_PLATFORM_CHANNEL_ID,
 };
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 fn create_album(args: CreateAlbumArg) -> Result<u64, String> {
     // Step 1: Load current state of Space.
     state::load();
@@ -80,7 +94,7 @@ fn create_album(args: CreateAlbumArg) -> Result<u64, String> {
     album_id
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 fn edit_album(id: u64, args: EditAlbumArg) -> Result<(), String> {
     if let Some(existing_album) = store::album::get_album(id) {
         let updated_sub_category = if let Some(sub_category) = args.sub_category {
@@ -88,6 +102,12 @@ fn edit_album(id: u64, args: EditAlbumArg) -> Result<(), String> {
         } else {
             existing_album.0.sub_category
         };
+        // The release time of a scheduled album is only changed by schedule_album_release
+        if args.release_at.is_some() && existing_album.0.release_status.is_some() {
+            return Err(
+                CustomError::new(ErrorCode::OperationNotAllowed, Some("release_at")).to_string(),
+            );
+        }
         let updated_release_at = if let Some(release_at) = args.release_at {
             Some(release_at)
         } else {
@@ -130,7 +150,62 @@ fn edit_album(id: u64, args: EditAlbumArg) -> Result<(), String> {
     }
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+// Keeps the album as a draft until release_at, then publishes it and optionally
+// shares its tracks to a platform channel.
+#[ic_cdk::update(guard = "publisher_guard")]
+fn schedule_album_release(
+    album_id: u64,
+    release_at: u64,
+    channel_id: Option<u64>,
+) -> Result<(), String> {
+    let mut album = store::album::get_album(album_id)
+        .ok_or_else(|| CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string())?
+        .into_inner();
+    if album.status == AlbumStatus::Deleted {
+        return Err(CustomError::new(ErrorCode::OperationNotAllowed, Some("Album")).to_string());
+    }
+    if release_at <= time() {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("release_at")).to_string());
+    }
+
+    // Subscription albums stay gated once released.
+    let release_status = match album.status {
+        AlbumStatus::Subscription => AlbumStatus::Subscription,
+        AlbumStatus::Draft => album.release_status.clone().unwrap_or(AlbumStatus::Public),
+        _ => AlbumStatus::Public,
+    };
+    album.status = AlbumStatus::Draft;
+    album.release_status = Some(release_status);
+    album.release_at = Some(release_at);
+    album.release_channel_id = channel_id;
+    album.updated = time();
+    store::album::edit_album(album_id, album)?;
+
+    store::release::schedule_next_release();
+    Ok(())
+}
+
+// The album stays a draft without a release time.
+#[ic_cdk::update(guard = "publisher_guard")]
+fn cancel_album_release(album_id: u64) -> Result<(), String> {
+    let mut album = store::album::get_album(album_id)
+        .ok_or_else(|| CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string())?
+        .into_inner();
+    if album.status != AlbumStatus::Draft || album.release_status.is_none() {
+        return Err(CustomError::new(ErrorCode::OperationNotAllowed, Some("release")).to_string());
+    }
+
+    album.release_at = None;
+    album.release_channel_id = None;
+    album.release_status = None;
+    album.updated = time();
+    store::album::edit_album(album_id, album)?;
+
+    store::release::schedule_next_release();
+    Ok(())
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
 fn delete_album(album_id: u64) -> Result<String, String> {
     state::load();
 
@@ -160,7 +235,96 @@ fn delete_album(album_id: u64) -> Result<String, String> {
     result
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
+fn create_category(args: CategoryArgs) -> Result<u32, String> {
+    state::load();
+    let id = store::category::create_category(args)?;
+    state::save();
+
+    Ok(id)
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
+fn update_category(id: u32, args: CategoryArgs) -> Result<(), String> {
+    state::load();
+    store::category::update_category(id, args)?;
+    state::save();
+
+    Ok(())
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
+fn delete_category(id: u32) -> Result<(), String> {
+    state::load();
+    store::category::delete_category(id)?;
+    state::save();
+
+    Ok(())
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
+fn set_album_category(album_id: u64, category_id: Option<u32>) -> Result<(), String> {
+    check_category(category_id)?;
+    let mut album = store::album::get_album(album_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string())?
+        .into_inner();
+    album.category_id = category_id;
+    album.updated = time();
+    store::album::edit_album(album_id, album)
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
+fn set_track_category(track_id: u64, category_id: Option<u32>) -> Result<(), String> {
+    check_category(category_id)?;
+    let mut track = store::track::get_track(track_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Track")).to_string())?
+        .into_inner();
+    track.category_id = category_id;
+    track.updated = time();
+    store::track::edit_track(track_id, track)
+}
+
+fn check_category(category_id: Option<u32>) -> Result<(), String> {
+    match category_id {
+        Some(id) if !store::category::exists(id) => {
+            Err(CustomError::new(ErrorCode::NoDataFound, Some("Category")).to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+// An empty split sheet clears it, so the whole income stays with the space.
+#[ic_cdk::update(guard = "owner_guard")]
+fn set_album_revenue_shares(album_id: u64, shares: Vec<RevenueShare>) -> Result<(), String> {
+    validate_split_sheet(&shares)?;
+    let mut album = store::album::get_album(album_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string())?
+        .into_inner();
+    album.revenue_shares = (!shares.is_empty()).then_some(shares);
+    album.updated = time();
+    store::album::edit_album(album_id, album)
+}
+
+#[ic_cdk::update(guard = "owner_guard")]
+fn set_track_revenue_shares(track_id: u64, shares: Vec<RevenueShare>) -> Result<(), String> {
+    validate_split_sheet(&shares)?;
+    let mut track = store::track::get_track(track_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Track")).to_string())?
+        .into_inner();
+    track.revenue_shares = (!shares.is_empty()).then_some(shares);
+    track.updated = time();
+    store::track::edit_track(track_id, track)
+}
+
+fn validate_split_sheet(shares: &[RevenueShare]) -> Result<(), String> {
+    if shares.is_empty() {
+        return Ok(());
+    }
+    validate_revenue_shares(shares)
+        .map_err(|err| CustomError::new(ErrorCode::ParamsError, Some(&err)).to_string())
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
 fn create_track(args: CreateTrackArg) -> Result<u64, String> {
     // Step 1: Load current state of Space.
     state::load();
@@ -221,8 +385,9 @@ fn create_track(args: CreateTrackArg) -> Result<u64, String> {
     track_id
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 fn edit_track(id: u64, args: EditTrackArg) -> Result<(), String> {
+    args.validate()?;
     if let Some(existing_track) = store::track::get_track(id) {
         let updated_versions = if let Some(versions) = args.versions {
             Some(versions)
@@ -252,6 +417,8 @@ fn edit_track(id: u64, args: EditTrackArg) -> Result<(), String> {
             duration: updated_duration,
             file_size: args.file_size.unwrap_or(existing_track.0.file_size),
             file_format: args.file_format.unwrap_or(existing_track.0.file_format),
+            disc_number: args.disc_number.or(existing_track.0.disc_number),
+            side: args.side.or(existing_track.0.side),
             created: existing_track.0.created,
             updated: ic_cdk::api::time(),
             ..existing_track.0
@@ -264,7 +431,7 @@ fn edit_track(id: u64, args: EditTrackArg) -> Result<(), String> {
     }
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 fn add_track_attribute(track_id: u64, new_attribute: Attribute) -> Result<bool, String> {
     if let Some(_) = store::track::get_track(track_id) {
         store::track::update_track_field(track_id, |track| {
@@ -287,7 +454,7 @@ fn add_track_attribute(track_id: u64, new_attribute: Attribute) -> Result<bool,
     }
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 fn delete_track(id: u64) -> Result<(), String> {
     state::load();
 
@@ -306,7 +473,7 @@ fn delete_track(id: u64) -> Result<(), String> {
     result
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 fn add_track_ids_to_album(album_id: u64, new_track_ids: Vec<u64>) -> Result<(), String> {
     // Step 1: Check new_track_ids exists.
     if new_track_ids.len() == 0 || store::track::check_track_ids(new_track_ids.clone()) == false {
@@ -333,6 +500,13 @@ fn add_track_ids_to_album(album_id: u64, new_track_ids: Vec<u64>) -> Result<(),
             }
 
             for track_id in unique_track_ids.clone() {
+                // A track belongs to a single album, detach it from the previous one.
+                if let Some(previous) = store::track::get_track(track_id)
+                    .and_then(|track| track.into_inner().album_id)
+                    .filter(|previous| *previous != album_id)
+                {
+                    store::album::remove_track_ids_from_album(previous, vec![track_id])?;
+                }
                 store::track::set_track_album_id(track_id, Some(album_id))?;
             }
 
@@ -342,7 +516,7 @@ fn add_track_ids_to_album(album_id: u64, new_track_ids: Vec<u64>) -> Result<(),
     }
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 fn remove_track_ids_to_album(album_id: u64, remove_track_ids: Vec<u64>) -> Result<(), String> {
     if remove_track_ids.len() == 0
         || store::track::check_track_ids(remove_track_ids.clone()) == false
@@ -364,7 +538,40 @@ fn remove_track_ids_to_album(album_id: u64, remove_track_ids: Vec<u64>) -> Resul
     }
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
+fn reorder_album_tracks(album_id: u64, track_ids: Vec<u64>) -> Result<(), String> {
+    let mut album = store::album::get_album(album_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string())?
+        .into_inner();
+    album
+        .reorder_tracks(track_ids)
+        .map_err(|err| CustomError::new(ErrorCode::ParamsError, Some(&err)).to_string())?;
+
+    // Repair tracks whose album reference drifted from the tracklist.
+    for track in store::track::get_tracks_by_ids(album.track_ids.clone()) {
+        if track.album_id != Some(album_id) {
+            store::track::set_track_album_id(track.id, Some(album_id))?;
+        }
+    }
+
+    album.updated = time();
+    store::album::edit_album(album_id, album)
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
+fn move_album_track(album_id: u64, from: u32, to: u32) -> Result<(), String> {
+    let mut album = store::album::get_album(album_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string())?
+        .into_inner();
+    album
+        .move_track(from as usize, to as usize)
+        .map_err(|err| CustomError::new(ErrorCode::ParamsError, Some(&err)).to_string())?;
+
+    album.updated = time();
+    store::album::edit_album(album_id, album)
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
 async fn create_track_license(args: CreateTrackLicenseArg) -> Result<u64, String> {
     // Step 1: Retrieve both the track and associated album.
     let (track, album) = store::track::get_track_and_album(args.track_id)
@@ -430,7 +637,7 @@ async fn create_track_license(args: CreateTrackLicenseArg) -> Result<u64, String
     license_id
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 async fn delete_track_license(track_id: u64) -> Result<u64, String> {
     let (track, album) = store::track::get_track_and_album(track_id)
         .map_err(|err| format!("Failed to retrieve track and album: {}", err))?;
@@ -461,7 +668,60 @@ async fn delete_track_license(track_id: u64) -> Result<u64, String> {
     Ok(track_id)
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+// The genesis album license is created when the caller licenses the album to itself.
+#[ic_cdk::update(guard = "editor_guard")]
+fn create_album_license(args: CreateAlbumLicenseArg) -> Result<u64, String> {
+    if store::album::get_album(args.album_id).is_none() {
+        return Err(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string());
+    }
+
+    let user_pid = if args.user_pid == ic_cdk::caller() {
+        ic_cdk::id()
+    } else {
+        args.user_pid
+    };
+
+    state::load();
+    let license_id = state::with_mut(|space| {
+        let new_license_id = space.next_license_id;
+
+        store::license::add_license(
+            new_license_id,
+            Some(args.album_id),
+            None,
+            user_pid,
+            args.channel,
+            args.asset_type,
+            args.usage_rights,
+            args.licensed_media,
+            args.licensed_territory,
+            args.right_period,
+            args.fee,
+        );
+
+        space.total_licenses += 1;
+        space.next_license_id += 1;
+
+        new_license_id
+    });
+    state::save();
+
+    Ok(license_id)
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
+fn delete_album_license(album_id: u64) -> Result<u64, String> {
+    state::load();
+    let result = state::with_mut(|space| {
+        store::license::delete_license(Some(album_id), None)?;
+        space.total_licenses = space.total_licenses.saturating_sub(1);
+        Ok(album_id)
+    });
+    state::save();
+    result
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
 fn add_track_license_for_platform(track_id: u64) -> Result<u64, String> {
     state::load();
     let license_id = state::with_mut(|space| {
@@ -481,7 +741,7 @@ fn add_track_license_for_platform(track_id: u64) -> Result<u64, String> {
     license_id
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 fn remove_track_license_for_platform(track_id: u64) -> Result<(), String> {
     state::load();
     let result = state::with_mut(|space| {
@@ -499,12 +759,63 @@ fn remove_track_license_for_platform(track_id: u64) -> Result<(), String> {
     result
 }
 
+// Usage events are reported by the licensee, or by the platform on its behalf.
+#[ic_cdk::update(guard = "anonymous_guard")]
+fn submit_license_usage(events: Vec<LicenseUsageArg>) -> Result<Vec<u64>, String> {
+    if events.is_empty() || events.len() > MAX_LICENSE_USAGE_EVENTS {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("events")).to_string());
+    }
+
+    let reporter = caller();
+    let now = time();
+    let platform = state::get_env().get_canister_pid(CanisterType::Platform);
+    let is_platform = Principal::from_text(platform).map_or(false, |pid| pid == reporter);
+
+    let mut records: Vec<LicenseRecord> = Vec::with_capacity(events.len());
+    for event in events {
+        event.validate()?;
+        let license = store::license::find_license(event.license_id)
+            .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("License")).to_string())?;
+        if license.user != reporter && !is_platform {
+            return Err(CustomError::new(ErrorCode::OperationNotAllowed, None).to_string());
+        }
+
+        let access_time = event.access_time.unwrap_or(now);
+        if access_time > now || access_time < license.start_time || !license.is_active(access_time)
+        {
+            return Err(CustomError::new(ErrorCode::ParamsError, Some("access_time")).to_string());
+        }
+
+        let mut record = LicenseRecord::new(0, license.id, license.user, access_time, event.action);
+        record.quantity = event.quantity;
+        record.reporter = (reporter != license.user).then_some(reporter);
+        records.push(record);
+    }
+
+    state::load();
+    let ids = state::with_mut(|space| {
+        records
+            .into_iter()
+            .map(|mut record| {
+                space.next_record_id += 1;
+                record.id = space.next_record_id;
+                store::license::add_license_record(record);
+                space.next_record_id
+            })
+            .collect()
+    });
+    state::save();
+
+    Ok(ids)
+}
+
 #[ic_cdk::update(guard = "owner_guard")]
 pub async fn canister_balance() -> Result<Tokens, String> {
     let account_identifier = canister_account();
+    let ledger_canister_id = token_ledger("ICP")?;
 
     let result: Result<(Tokens,), _> = ic_cdk::api::call::call(
-        MAINNET_LEDGER_CANISTER_ID,
+        ledger_canister_id,
         "account_balance",
         (AccountBalanceArgs {
             account: account_identifier,
@@ -517,10 +828,9 @@ pub async fn canister_balance() -> Result<Tokens, String> {
         .map_err(|_| CustomError::new(ErrorCode::FetchDataError, Some("Balance")).to_string())
 }
 
+// Moves ICP from the default account, earnings in the treasury subaccount are withdrawn with withdraw.
 #[ic_cdk::update(guard = "owner_guard")]
 async fn canister_transfer(args: TransferArgs) -> Result<bool, String> {
-    // let caller_pid = caller();
-    // let from_account = account_id(ic_cdk::id(), None);
     let balance_result = canister_balance();
 
     let balance = match balance_result.await {
@@ -528,21 +838,29 @@ async fn canister_transfer(args: TransferArgs) -> Result<bool, String> {
         Err(_) => return Err(CustomError::new(ErrorCode::BalanceRetrieveError, None).to_string()),
     };
 
-    if balance < (args.amount.e8s() + args.fee.e8s()) {
+    // The fee of the ledger of the current environment is charged, whatever the caller passed.
+    let fee = Tokens::from_e8s(token_fee("ICP"));
+    let total = args
+        .amount
+        .e8s()
+        .checked_add(fee.e8s())
+        .ok_or_else(|| CustomError::new(ErrorCode::ParamsError, Some("amount")).to_string())?;
+    if balance < total {
         return Ok(false);
     }
 
     let transfer_args = TransferArgs {
         memo: args.memo,
         amount: args.amount,
-        fee: args.fee,
+        fee,
         from_subaccount: None,
         to: args.to,
         created_at_time: None,
     };
 
+    let ledger_canister_id = token_ledger("ICP")?;
     let transfer_result: Result<(TransferResult,), _> =
-        ic_cdk::api::call::call(MAINNET_LEDGER_CANISTER_ID, "transfer", (transfer_args,)).await;
+        ic_cdk::api::call::call(ledger_canister_id, "transfer", (transfer_args,)).await;
 
     match transfer_result {
         Ok((TransferResult::Ok(_),)) => Ok(true),
@@ -551,7 +869,127 @@ async fn canister_transfer(args: TransferArgs) -> Result<bool, String> {
     }
 }
 
-#[ic_cdk::update(guard = "anonymous_guard")]
+#[ic_cdk::update(guard = "finance_guard")]
+async fn consolidate_earnings() -> Result<u32, String> {
+    let count = store::treasury::consolidate_paid_orders(time()).await;
+    Ok(count as u32)
+}
+
+#[ic_cdk::update(guard = "finance_guard")]
+async fn get_treasury_balances() -> Result<Vec<TreasuryBalance>, String> {
+    Ok(store::treasury::balances().await)
+}
+
+// Withdraws consolidated earnings from the treasury account, returns the block index.
+#[ic_cdk::update(guard = "finance_guard")]
+async fn withdraw(token: String, to: Account, amount: u64) -> Result<u64, String> {
+    if amount == 0 {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("amount")).to_string());
+    }
+    if !state::is_token_accepted(&token) {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("token")).to_string());
+    }
+
+    let total = amount
+        .checked_add(token_fee(&token))
+        .ok_or_else(|| CustomError::new(ErrorCode::ParamsError, Some("amount")).to_string())?;
+    let balance = token_balance(&token, store::treasury::treasury_account()).await;
+    if balance < total {
+        return Err(CustomError::new(ErrorCode::InsufficientFunds, None).to_string());
+    }
+
+    let height = account_transfer(&token, Some(TREASURY_SUBACCOUNT), to, amount)
+        .await
+        .map_err(|err| CustomError::new(ErrorCode::BalanceTransferError, Some(&err)).to_string())?;
+    store::journal::add_transfer(
+        JournalKind::Withdrawal,
+        None,
+        &token,
+        amount,
+        JournalAccount::Withdrawals,
+        JournalAccount::Treasury,
+        Some(height),
+    );
+    Ok(height)
+}
+
+// With an ICRC-2 checkout the caller pays from its own approved account.
+fn checkout_account(checkout: Option<Icrc2Checkout>) -> Result<Option<Account>, String> {
+    checkout
+        .map(|checkout| checkout.account(caller()))
+        .transpose()
+}
+
+// Orders are priced in ICP unless another accepted token is selected.
+fn license_order_price(license: &License, token: Option<String>) -> Result<(String, u64), String> {
+    let token = token.unwrap_or_else(|| "ICP".to_string());
+    if !state::is_token_accepted(&token) {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("token")).to_string());
+    }
+    let amount = license
+        .price_in(&token, SPACE_LICENSE_PRICE_ICP)
+        .ok_or(format!("License is not priced in {}", token))?;
+    Ok((token, amount))
+}
+
+#[ic_cdk::update(guard = "owner_guard")]
+async fn add_token(ledger_id: Principal) -> Result<TokenInfo, String> {
+    let token = fetch_token_info(ledger_id).await?;
+    if TokenInfo::is_builtin(&token.symbol) {
+        return Err(CustomError::new(ErrorCode::DataIsExists, Some(&token.symbol)).to_string());
+    }
+    if let Some(existing) = state::get_token(&token.symbol) {
+        if existing.ledger_id != ledger_id {
+            return Err(CustomError::new(ErrorCode::DataIsExists, Some(&token.symbol)).to_string());
+        }
+    }
+
+    state::load();
+    state::set_token(token.clone())?;
+    state::save();
+
+    Ok(token)
+}
+
+#[ic_cdk::update(guard = "owner_guard")]
+fn set_token_enabled(symbol: String, enabled: bool) -> Result<(), String> {
+    let mut token = state::get_token(&symbol)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Token")).to_string())?;
+    token.enabled = enabled;
+    token.updated = time();
+
+    state::load();
+    state::set_token(token)?;
+    state::save();
+
+    Ok(())
+}
+
+// Prices of the genesis license in registered tokens, the ICP price stays in `fee`.
+#[ic_cdk::update(guard = "editor_guard")]
+fn set_license_token_prices(license_id: u64, prices: Vec<TokenPrice>) -> Result<(), String> {
+    for (i, price) in prices.iter().enumerate() {
+        if price.price == 0
+            || price.token_name == "ICP"
+            || !state::is_token_accepted(&price.token_name)
+            || prices[..i].iter().any(|p| p.token_name == price.token_name)
+        {
+            return Err(
+                CustomError::new(ErrorCode::ParamsError, Some(&price.token_name)).to_string(),
+            );
+        }
+    }
+
+    store::license::update_license(license_id, |license| {
+        if license.user != ic_cdk::id() {
+            return Err("Only genesis licenses can be priced".to_string());
+        }
+        license.token_prices = (!prices.is_empty()).then_some(prices);
+        Ok(())
+    })
+}
+
+#[ic_cdk::update(guard = "blacklist_guard")]
 fn create_award_order(
     source: String,
     token: String,
@@ -559,13 +997,16 @@ fn create_award_order(
     payment_type: PaymentType,
 ) -> Result<Option<PaymentInfo>, String> {
     let payer = caller();
+    if !state::is_token_accepted(&token) {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("token")).to_string());
+    }
     let mut payment_info: Option<PaymentInfo> = None;
 
     state::load();
     state::with_mut(|space| {
         let new_order_id = space.next_order_id + 1;
 
-        payment_info = Some(store::payment::create_award_order(
+        payment_info = Some(store::payment::create_payment_order(
             new_order_id,
             payer,
             source,
@@ -582,17 +1023,26 @@ fn create_award_order(
     Ok(payment_info)
 }
 
-#[ic_cdk::update(guard = "anonymous_guard")]
-fn create_license_order(track_id: u64, source: String) -> Result<Option<PaymentInfo>, String> {
+#[ic_cdk::update(guard = "blacklist_guard")]
+fn create_license_order(
+    track_id: u64,
+    source: String,
+    token: Option<String>,
+) -> Result<Option<PaymentInfo>, String> {
     let payer = caller();
 
     let license = match store::license::get_track_genesis_license(track_id) {
         Some(license) => license,
         None => return Err("Genesis license not found for the provided track ID.".to_string()),
     };
-    let amount = license.fee.unwrap_or(SPACE_LICENSE_PRICE_ICP as u128) as u64;
-    let token = "ICP".to_string();
-    let payment_type = PaymentType::LicensePrice(LicensePrice::new_for_license(track_id, amount));
+    if store::license::has_active_license(&LicenseKey::new(None, Some(track_id)), payer, time()) {
+        return Err(CustomError::new(ErrorCode::DataIsExists, Some("License")).to_string());
+    }
+    let (token, amount) = license_order_price(&license, token)?;
+    let payment_type = PaymentType::LicensePrice(LicensePrice {
+        token_name: token.clone(),
+        ..LicensePrice::new_for_license(track_id, amount)
+    });
 
     let mut payment_info: Option<PaymentInfo> = None;
 
@@ -600,7 +1050,7 @@ fn create_license_order(track_id: u64, source: String) -> Result<Option<PaymentI
     state::with_mut(|space| {
         let new_order_id = space.next_order_id + 1;
 
-        payment_info = Some(store::payment::create_award_order(
+        payment_info = Some(store::payment::create_payment_order(
             new_order_id,
             payer,
             source,
@@ -617,18 +1067,136 @@ fn create_license_order(track_id: u64, source: String) -> Result<Option<PaymentI
     Ok(payment_info)
 }
 
+#[ic_cdk::update(guard = "blacklist_guard")]
+fn create_album_license_order(
+    album_id: u64,
+    source: String,
+    token: Option<String>,
+) -> Result<Option<PaymentInfo>, String> {
+    let payer = caller();
+
+    let license = store::license::get_album_genesis_license(album_id)
+        .ok_or("Genesis license not found for the provided album ID.")?;
+    if store::license::has_active_license(&LicenseKey::new(Some(album_id), None), payer, time()) {
+        return Err(CustomError::new(ErrorCode::DataIsExists, Some("License")).to_string());
+    }
+    let (token, amount) = license_order_price(&license, token)?;
+    let payment_type = PaymentType::LicensePrice(LicensePrice {
+        token_name: token.clone(),
+        ..LicensePrice::new_for_album_license(album_id, amount)
+    });
+
+    let mut payment_info: Option<PaymentInfo> = None;
+
+    state::load();
+    state::with_mut(|space| {
+        let new_order_id = space.next_order_id + 1;
+
+        payment_info = Some(store::payment::create_payment_order(
+            new_order_id,
+            payer,
+            source,
+            token,
+            amount,
+            payment_type,
+        ));
+
+        space.total_orders += 1;
+        space.next_order_id += 1;
+    });
+    state::save();
+
+    Ok(payment_info)
+}
+
+// The album license covers every track of the album, including tracks added later.
 #[ic_cdk::update(guard = "anonymous_guard")]
-async fn confirm_award_order(pay_id: u64) -> Result<bool, String> {
-    let result = store::payment::confirm_payment_order(pay_id).await;
-    result
+async fn confirm_album_license_order(
+    pay_id: u64,
+    checkout: Option<Icrc2Checkout>,
+) -> Result<u64, String> {
+    let order = store::payment::get_payment_order(pay_id).ok_or("Order not found")?;
+    let album_id = match &order.payment_type {
+        PaymentType::LicensePrice(price) if price.license_id.is_none() => price
+            .album_id
+            .ok_or(CustomError::new(ErrorCode::DataInvalid, Some("album_id")).to_string())?,
+        _ => {
+            return Err(CustomError::new(
+                ErrorCode::DataInvalid,
+                Some("Payment type is not an album LicensePrice"),
+            )
+            .to_string())
+        }
+    };
+
+    let genesis_license = store::license::get_album_genesis_license(album_id)
+        .ok_or("Genesis license not found for the given album_id")?;
+
+    store::payment::confirm_payment_order(pay_id, checkout_account(checkout)?).await?;
+
+    // Grant the license only once per order.
+    let order = store::payment::deliver_payment_order(pay_id)?;
+
+    state::load();
+    let new_license_id = state::with_mut(|space| {
+        let new_license_id = space.next_license_id;
+
+        // An active license of the payer is kept as it is, a lapsed or revoked one is replaced.
+        if !store::license::add_license(
+            new_license_id,
+            Some(album_id),
+            None,
+            order.payer,
+            genesis_license.channel,
+            genesis_license.asset_type,
+            genesis_license.usage_rights,
+            genesis_license.licensed_media,
+            genesis_license.licensed_territory,
+            genesis_license.right_period,
+            genesis_license.fee,
+        ) {
+            return Err("Payer already holds an active license for the album".to_string());
+        }
+
+        space.total_licenses += 1;
+        space.next_license_id += 1;
+
+        Ok(new_license_id)
+    })?;
+    state::save();
+
+    Ok(new_license_id)
+}
+
+#[ic_cdk::update(guard = "anonymous_guard")]
+async fn confirm_award_order(pay_id: u64, checkout: Option<Icrc2Checkout>) -> Result<bool, String> {
+    store::payment::confirm_payment_order(pay_id, checkout_account(checkout)?).await?;
+
+    // Record an award only once per order, repeated confirmations are no-ops.
+    let order = store::payment::get_payment_order(pay_id).ok_or("Order not found")?;
+    let album_id = match &order.payment_type {
+        PaymentType::Award(price) if order.delivered_time.is_none() => price.aid.clone(),
+        _ => return Ok(true),
+    };
+    let order = store::payment::deliver_payment_order(pay_id)?;
+    store::award::add_award(AwardOrder::from_payment_order(&order, album_id, time()))?;
+
+    Ok(true)
 }
 
 #[ic_cdk::update(guard = "anonymous_guard")]
-async fn confirm_license_order(pay_id: u64) -> Result<u64, String> {
+async fn confirm_license_order(
+    pay_id: u64,
+    checkout: Option<Icrc2Checkout>,
+) -> Result<u64, String> {
     // Step 1: Retrieve the order and ensure it is a LicensePrice order.
     let order = store::payment::get_payment_order(pay_id).ok_or("Order not found")?;
     let track_id = match &order.payment_type {
-        PaymentType::LicensePrice(license_price) => license_price.track_id,
+        PaymentType::LicensePrice(license_price)
+            if license_price.license_id.is_none() && license_price.album_id.is_none() =>
+        {
+            license_price.track_id
+        }
         _ => {
             return Err(CustomError::new(
                 ErrorCode::DataInvalid,
@@ -643,11 +1211,15 @@ async fn confirm_license_order(pay_id: u64) -> Result<u64, String> {
         .ok_or("Genesis license not found for the given track_id")?;
 
     // Step 3: Confirm the payment order.
-    if !store::payment::confirm_payment_order(pay_id).await? {
+    if !store::payment::confirm_payment_order(pay_id, checkout_account(checkout)?).await? {
         return Ok(0);
     }
 
-    // Step 4: Clone the genesis license, update the user, and save as a new license.
+    // Step 4: Grant the license only once per order.
+    let order = store::payment::deliver_payment_order(pay_id)?;
+
+    // Step 5: Clone the genesis license, update the user, and save as a new license.
+    state::load();
     let new_license_id = state::with_mut(|space| {
         let new_license_id = space.next_license_id;
 
@@ -655,8 +1227,8 @@ async fn confirm_license_order(pay_id: u64) -> Result<u64, String> {
         let mut new_license = genesis_license.clone();
         new_license.user = order.payer;
 
-        // Add the new license.
-        store::license::add_license(
+        // Add the new license, an active license of the payer is kept as it is.
+        if !store::license::add_license(
             new_license_id,
             None,
             Some(track_id),
@@ -668,20 +1240,207 @@ async fn confirm_license_order(pay_id: u64) -> Result<u64, String> {
             new_license.licensed_territory,
             new_license.right_period,
             new_license.fee,
-        );
+        ) {
+            return Err("Payer already holds an active license for the track".to_string());
+        }
 
         // Update license counters.
         space.total_licenses += 1;
         space.next_license_id += 1;
 
-        new_license_id
-    });
+        Ok(new_license_id)
+    })?;
 
     state::save();
 
     Ok(new_license_id)
 }
 
+#[ic_cdk::update(guard = "editor_guard")]
+fn revoke_license(license_id: u64) -> Result<(), String> {
+    store::license::revoke_license(license_id, time())
+}
+
+#[ic_cdk::update(guard = "blacklist_guard")]
+fn renew_license(license_id: u64, token: Option<String>) -> Result<Option<PaymentInfo>, String> {
+    let payer = caller();
+
+    let license = store::license::find_license(license_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("License")).to_string())?;
+    if license.user != payer {
+        return Err(CustomError::new(ErrorCode::OperationNotAllowed, None).to_string());
+    }
+    if license.revoke_time.is_some() {
+        return Err("License has been revoked".to_string());
+    }
+    if license.valid_duration.is_none() {
+        return Err("License is perpetual and cannot be renewed".to_string());
+    }
+
+    // Renewals are charged at the current price of the genesis license.
+    let (track_id, genesis_license) = match license.resource_key {
+        LicenseKey {
+            album_id: Some(album_id),
+            ..
+        } => (0, store::license::get_album_genesis_license(album_id)),
+        LicenseKey {
+            track_id: Some(track_id),
+            ..
+        } => (
+            track_id,
+            store::license::get_track_genesis_license(track_id),
+        ),
+        _ => return Err(CustomError::new(ErrorCode::DataInvalid, Some("License")).to_string()),
+    };
+    let genesis_license = genesis_license.ok_or("Genesis license not found for the license.")?;
+    let (token, amount) = license_order_price(&genesis_license, token)?;
+    let payment_type = PaymentType::LicensePrice(LicensePrice {
+        album_id: license.resource_key.album_id,
+        token_name: token.clone(),
+        ..LicensePrice::new_for_renewal(track_id, license_id, amount)
+    });
+
+    let mut payment_info: Option<PaymentInfo> = None;
+
+    state::load();
+    state::with_mut(|space| {
+        let new_order_id = space.next_order_id + 1;
+
+        payment_info = Some(store::payment::create_payment_order(
+            new_order_id,
+            payer,
+            license_id.to_string(),
+            token,
+            amount,
+            payment_type,
+        ));
+
+        space.total_orders += 1;
+        space.next_order_id += 1;
+    });
+    state::save();
+
+    Ok(payment_info)
+}
+
+// Returns the new expiry time of the renewed license.
+#[ic_cdk::update(guard = "anonymous_guard")]
+async fn confirm_renew_license(
+    pay_id: u64,
+    checkout: Option<Icrc2Checkout>,
+) -> Result<u64, String> {
+    let order = store::payment::get_payment_order(pay_id).ok_or("Order not found")?;
+    if !matches!(&order.payment_type, PaymentType::LicensePrice(price) if price.license_id.is_some())
+    {
+        return Err(CustomError::new(
+            ErrorCode::DataInvalid,
+            Some("Payment type is not a license renewal"),
+        )
+        .to_string());
+    }
+
+    store::payment::confirm_payment_order(pay_id, checkout_account(checkout)?).await?;
+
+    // Extend the license only once per order.
+    let order = store::payment::deliver_payment_order(pay_id)?;
+    let license_id = match order.payment_type {
+        PaymentType::LicensePrice(price) => price.license_id.unwrap_or_default(),
+        _ => return Err(CustomError::new(ErrorCode::DataInvalid, None).to_string()),
+    };
+
+    store::license::update_license(license_id, |license| {
+        if license.revoke_time.is_some() {
+            return Err("License has been revoked".to_string());
+        }
+        license.renew(time())
+    })
+}
+
+#[ic_cdk::update(guard = "blacklist_guard")]
+fn create_subscription_order(
+    album_id: u64,
+    sub_type: SubscriptionType,
+    token: Option<String>,
+) -> Result<Option<PaymentInfo>, String> {
+    let payer = caller();
+    // Orders are priced in ICP unless another accepted token is selected.
+    let token = token.unwrap_or_else(|| "ICP".to_string());
+    if !state::is_token_accepted(&token) {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("token")).to_string());
+    }
+
+    let album = store::album::get_album(album_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string())?
+        .into_inner();
+    if album.status != AlbumStatus::Subscription {
+        return Err(CustomError::new(ErrorCode::OperationNotAllowed, Some("Album")).to_string());
+    }
+
+    let price = album
+        .subscription_prices
+        .into_iter()
+        .find(|price| {
+            price.sub_type == sub_type
+                && sub_type != SubscriptionType::Free
+                && price.token() == token
+        })
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("SubscriptionPrice")).to_string())?;
+    let amount = price.price;
+    let payment_type = PaymentType::Price(price);
+
+    let mut payment_info: Option<PaymentInfo> = None;
+
+    state::load();
+    state::with_mut(|space| {
+        let new_order_id = space.next_order_id + 1;
+
+        payment_info = Some(store::payment::create_payment_order(
+            new_order_id,
+            payer,
+            album_id.to_string(),
+            token,
+            amount,
+            payment_type,
+        ));
+
+        space.total_orders += 1;
+        space.next_order_id += 1;
+    });
+    state::save();
+
+    Ok(payment_info)
+}
+
+#[ic_cdk::update(guard = "anonymous_guard")]
+async fn confirm_subscription_order(
+    pay_id: u64,
+    checkout: Option<Icrc2Checkout>,
+) -> Result<SubscriberInfo, String> {
+    // Step 1: Retrieve the order and ensure it is a subscription order.
+    let order = store::payment::get_payment_order(pay_id).ok_or("Order not found")?;
+    if !matches!(order.payment_type, PaymentType::Price(_)) {
+        return Err(
+            CustomError::new(ErrorCode::DataInvalid, Some("Payment type is not Price")).to_string(),
+        );
+    }
+    let album_id = order
+        .source
+        .parse::<u64>()
+        .map_err(|_| CustomError::new(ErrorCode::DataInvalid, Some("album_id")).to_string())?;
+
+    // Step 2: Confirm the payment order.
+    store::payment::confirm_payment_order(pay_id, checkout_account(checkout)?).await?;
+
+    // Step 3: Grant the subscription only once per order.
+    let order = store::payment::deliver_payment_order(pay_id)?;
+    let price = match order.payment_type {
+        PaymentType::Price(price) => price,
+        _ => return Err(CustomError::new(ErrorCode::DataInvalid, None).to_string()),
+    };
+
+    store::album::add_subscriber(album_id, Subscriber::new(order.payer, price))
+}
+
 #[ic_cdk::update(guard = "anonymous_guard")]
 async fn refund_payment_order(pay_id: u64, to: Vec<u8>) -> Result<bool, String> {
     let from = caller();
@@ -689,7 +1448,7 @@ async fn refund_payment_order(pay_id: u64, to: Vec<u8>) -> Result<bool, String>
     result
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "editor_guard")]
 async fn create_music_certificate(track_id: u64) -> Result<MusicCertificateResp, String> {
     let track_info = match store::track::get_track(track_id) {
         Some(track) => track.into_inner(),
@@ -754,12 +1513,66 @@ async fn update_dao_canister(dao_canister: Principal) -> Result<Principal, Strin
 }
 
 #[ic_cdk::update(guard = "owner_guard")]
-async fn add_managers(new_managers: Vec<Principal>) -> Result<(), String> {
-    state::add_managers(new_managers);
+fn add_managers(new_managers: Vec<Principal>, roles: Vec<ManagerRole>) -> Result<(), String> {
+    if roles.is_empty() {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("roles")).to_string());
+    }
+    check_manager_pids(&new_managers)?;
+    apply_manager_roles(new_managers, roles);
     Ok(())
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "owner_guard")]
+fn set_manager_roles(pid: Principal, roles: Vec<ManagerRole>) -> Result<(), String> {
+    if roles.is_empty() {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("roles")).to_string());
+    }
+    check_manager_pids(&[pid])?;
+    apply_manager_roles(vec![pid], roles);
+    Ok(())
+}
+
+#[ic_cdk::update(guard = "owner_guard")]
+fn remove_managers(pids: Vec<Principal>) -> Result<(), String> {
+    check_manager_pids(&pids)?;
+    let pids: Vec<Principal> = state::with(|space| {
+        pids.into_iter()
+            .filter(|pid| space.managers.contains(pid))
+            .collect()
+    });
+    apply_manager_roles(pids, vec![]);
+    Ok(())
+}
+
+// The whole list is checked before any role changes.
+fn check_manager_pids(pids: &[Principal]) -> Result<(), String> {
+    let owner = state::with(|space| space.owner);
+    if pids
+        .iter()
+        .any(|pid| *pid == Principal::anonymous() || *pid == owner)
+    {
+        return Err(CustomError::new(ErrorCode::OperationNotAllowed, Some("manager")).to_string());
+    }
+    Ok(())
+}
+
+// Every role change is recorded in the audit log.
+fn apply_manager_roles(pids: Vec<Principal>, roles: Vec<ManagerRole>) {
+    let pids: BTreeSet<Principal> = pids.into_iter().collect();
+
+    state::load();
+    for pid in &pids {
+        state::set_manager_roles(*pid, roles.clone());
+    }
+    state::save();
+
+    for pid in pids {
+        let roles = state::with(|space| space.manager_roles(&pid));
+        store::role_audit::add_entry(caller(), pid, roles);
+    }
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
 async fn add_contract_services(new_service: String) -> Result<String, String> {
     store::state::with_mut(|r| {
         if r.services.contains(&new_service) {
@@ -775,7 +1588,7 @@ async fn add_contract_services(new_service: String) -> Result<String, String> {
     })
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "publisher_guard")]
 async fn create_post(content: String) -> Result<String, String> {
     let mut new_post_id: u64 = 0;
     state::load();
@@ -810,7 +1623,7 @@ async fn create_post(content: String) -> Result<String, String> {
     send_post_message(msg_user_post, new_post_id, msg_resource).await
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "publisher_guard")]
 fn delete_post(post_id: u64) -> Result<(), String> {
     state::load();
 
@@ -826,7 +1639,230 @@ fn delete_post(post_id: u64) -> Result<(), String> {
     Ok(())
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "blacklist_guard")]
+async fn follow() -> Result<u64, String> {
+    set_follow(true).await
+}
+
+#[ic_cdk::update(guard = "anonymous_guard")]
+async fn unfollow() -> Result<u64, String> {
+    set_follow(false).await
+}
+
+async fn set_follow(follow: bool) -> Result<u64, String> {
+    let user_pid = caller();
+
+    if !store::follower::try_lock(user_pid) {
+        return Err(CustomError::new(
+            ErrorCode::OperationNotAllowed,
+            Some("A follow update is in progress"),
+        )
+        .to_string());
+    }
+    let res = update_follow(user_pid, follow).await;
+    store::follower::unlock(&user_pid);
+    res
+}
+
+// Runs under the follow lock of the user, so the rollback cannot undo a later change.
+async fn update_follow(user_pid: Principal, follow: bool) -> Result<u64, String> {
+    if !store::follower::set_follower(user_pid, follow) {
+        return Ok(store::follower::total());
+    }
+    sync_total_followers();
+
+    // The follow is undone when the user canister cannot record it, so both lists stay in sync.
+    if let Err(err) = notify_followed_space(user_pid, follow).await {
+        store::follower::set_follower(user_pid, !follow);
+        sync_total_followers();
+        return Err(CustomError::new(ErrorCode::RemoteCallUpdateError, Some(&err)).to_string());
+    }
+
+    state::load();
+    let (total_followers, message_id) = state::with_mut(|space| {
+        let message_id = space.next_message_id;
+        space.next_message_id += 1;
+        (space.total_followers, message_id)
+    });
+    state::save();
+
+    let msg_follow_space = MsgFollowSpace {
+        space_id: ic_cdk::id(),
+        user_pid,
+        follow,
+        created_at: time(),
+    };
+    // A failed delivery is kept locally and retried by the message timer.
+    let _ = store::message::send_message(
+        MessageType::Create,
+        "MsgFollowSpace",
+        message_id,
+        msg_follow_space,
+        None,
+    )
+    .await;
+
+    Ok(total_followers)
+}
+
+fn sync_total_followers() {
+    state::load();
+    state::with_mut(|space| space.total_followers = store::follower::total());
+    state::save();
+}
+
+async fn notify_followed_space(user_pid: Principal, follow: bool) -> Result<bool, String> {
+    let env = state::get_env();
+    let user_service = CanisterService::init(&env, &CanisterType::User)?;
+    match user_service.update_followed_space(user_pid, follow).await {
+        Ok((result,)) => result,
+        Err((_, msg)) => Err(msg),
+    }
+}
+
+#[ic_cdk::update(guard = "blacklist_guard")]
+fn create_comment(args: CommentArgs) -> Result<QueryComment, String> {
+    add_comment(args, None)
+}
+
+#[ic_cdk::update(guard = "blacklist_guard")]
+fn reply_comment(comment_id: u32, args: CommentArgs) -> Result<QueryComment, String> {
+    add_comment(args, Some(comment_id))
+}
+
+fn add_comment(args: CommentArgs, reply_to: Option<u32>) -> Result<QueryComment, String> {
+    let content = args.content.trim().to_string();
+    if content.is_empty() || content.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(CustomError::new(ErrorCode::ParamsError, Some("content")).to_string());
+    }
+
+    let album_id = parse_album_id(&args.album_id)?;
+    let mut album = store::album::get_album(album_id)
+        .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string())?
+        .into_inner();
+    if !album.allow_comments {
+        return Err(
+            CustomError::new(ErrorCode::OperationNotAllowed, Some("allow_comments")).to_string(),
+        );
+    }
+    if matches!(album.status, AlbumStatus::Draft | AlbumStatus::Deleted) {
+        return Err(CustomError::new(ErrorCode::OperationNotAllowed, Some("Album")).to_string());
+    }
+
+    // Fans only comment on what they can listen to.
+    let caller = caller();
+    state::with(|space| space.read_permission(caller))?;
+    let is_manager = state::with(|space| space.write_permission(caller).is_ok());
+    match store::album::album_access(&album, caller, is_manager) {
+        AlbumAccess::Full => {}
+        AlbumAccess::Metadata => {
+            return Err(CustomError::new(
+                ErrorCode::OperationNotAllowed,
+                Some("Subscription required"),
+            )
+            .to_string())
+        }
+        AlbumAccess::Hidden => {
+            return Err(CustomError::new(ErrorCode::NoDataFound, Some("Album")).to_string())
+        }
+    }
+
+    // Replies are kept one level deep: a reply to a reply joins the same thread.
+    let parent_id = match reply_to {
+        Some(id) => {
+            let parent = store::comment::get_comment(album_id, id)
+                .ok_or(CustomError::new(ErrorCode::NoDataFound, Some("Comment")).to_string())?
+                .into_inner();
+            if parent.status != CommentStatus::Visible {
+                return Err(
+                    CustomError::new(ErrorCode::OperationNotAllowed, Some("Comment")).to_string(),
+                );
+            }
+            Some(parent.parent_id.unwrap_or(parent.id))
+        }
+        None => None,
+    };
+
+    state::load();
+    let comment = state::with_mut(|space| {
+        let comment = Comment {
+            id: space.next_comment_id,
+            album_id: args.album_id.clone(),
+            owner: space.owner,
+            commenter_id: caller,
+            content,
+            created: time(),
+            likes: 0,
+            status: CommentStatus::Visible,
+            reply: None,
+            parent_id,
+            reply_count: 0,
+        };
+        space.next_comment_id += 1;
+        comment
+    });
+    state::save();
+
+    if let Some(parent_id) = parent_id {
+        store::comment::update_comment(album_id, parent_id, |parent| {
+            parent.reply_count += 1;
+            Ok(())
+        })?;
+    }
+    store::comment::add_comment(album_id, comment.clone());
+
+    album.comments += 1;
+    store::album::edit_album(album_id, album)?;
+
+    Ok(comment.to_query(None))
+}
+
+#[ic_cdk::update(guard = "blacklist_guard")]
+fn like_comment(album_id: String, comment_id: u32) -> Result<u32, String> {
+    let album_id = parse_album_id(&album_id)?;
+    store::comment::toggle_like(album_id, comment_id, caller())
+}
+
+#[ic_cdk::update(guard = "moderator_guard")]
+fn set_comment_status(
+    album_id: String,
+    comment_id: u32,
+    status: CommentStatus,
+) -> Result<(), String> {
+    let album_id = parse_album_id(&album_id)?;
+    store::comment::update_comment(album_id, comment_id, |comment| {
+        comment.status = status;
+        Ok(())
+    })
+}
+
+#[ic_cdk::update(guard = "moderator_guard")]
+async fn add_blacklist(pids: Vec<Principal>) -> Result<(), String> {
+    state::load();
+    state::add_blacklist(pids.clone(), time())?;
+    state::save();
+    let followers = store::follower::remove_followers(&pids);
+    sync_total_followers();
+
+    // Blocked users no longer follow the space.
+    for pid in followers {
+        if let Err(err) = notify_followed_space(pid, false).await {
+            ic_cdk::print(format!("Failed to unfollow blocked user {}: {}", pid, err));
+        }
+    }
+    Ok(())
+}
+
+#[ic_cdk::update(guard = "moderator_guard")]
+fn remove_blacklist(pids: Vec<Principal>) -> Result<(), String> {
+    state::load();
+    state::remove_blacklist(pids);
+    state::save();
+
+    Ok(())
+}
+
+#[ic_cdk::update(guard = "editor_guard")]
 async fn remove_contract_services(service_to_remove: String) -> Result<String, String> {
     store::state::with_mut(|r| {
         if let Some(pos) = r.services.iter().position(|s| *s == service_to_remove) {
@@ -857,7 +1893,7 @@ async fn remote_get_dao_info() -> Result<DaoStateInfo, String> {
     }
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "publisher_guard")]
 async fn remote_share_track_to_platform(
     track_id: u64,
     channel_id: Option<u64>,
@@ -919,7 +1955,7 @@ async fn remote_share_track_to_platform(
     }
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "publisher_guard")]
 async fn remote_batch_share_track_to_platform(
     track_ids: Vec<u64>,
     channel_id: Option<u64>,
@@ -988,7 +2024,7 @@ async fn remote_batch_share_track_to_platform(
     }
 }
 
-#[ic_cdk::update(guard = "write_guard")]
+#[ic_cdk::update(guard = "publisher_guard")]
 async fn remote_delete_share_track_to_platform(track_id: u64) -> Result<(), String> {
     let shared_track = store::share::get_share(track_id)
         .ok_or_else(|| format!("Share for track ID {} not found", track_id))?;
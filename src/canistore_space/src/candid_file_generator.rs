@@ -8,15 +8,28 @@ use canistore_types::{
     canister::{StatusRequest, StatusResponse},
     certificate::MusicCertificateResp,
     dao::DaoStateInfo,
-    license::{CreateTrackLicenseArg, LicenseListEntry, LicenseTrackListEntry, QueryLicenseResp},
-    payment::{PaymentInfo, PaymentType, QueryCommonReq, QueryOrderResp},
+    license::{
+        CreateAlbumLicenseArg, CreateTrackLicenseArg, LicenseListEntry, LicenseTrackListEntry,
+        LicenseUsageArg, LicenseUsageReport, QueryLicenseRecordResp, QueryLicenseResp,
+        TrackUsageReport,
+    },
+    payment::{
+        AwardSupporter, Icrc2Checkout, JournalBalance, PaymentInfo, PaymentType, PayoutRecord,
+        QueryAwardReq, QueryAwardResp, QueryCommonReq, QueryCommonSubscriber, QueryJournalReq,
+        QueryJournalResp, QueryOrderResp, QueryPayoutResp, QuerySelfSubscriber, RevenueShare,
+        SubscriberInfo, SubscriptionType, TokenInfo, TokenPrice, TreasuryBalance,
+    },
     space::{
-        Album, AlbumListEntry, CanisterArgs, CreateAlbumArg, CreateTrackArg, EditAlbumArg,
-        EditTrackArg, QueryTrackResp, SharedTrackListResp, Track, UserPost,
+        Album, AlbumListEntry, CanisterArgs, CategoryArgs, CommentArgs, CommentStatus,
+        CreateAlbumArg, CreateTrackArg, EditAlbumArg, EditTrackArg, ManagerRole, QueryAlbumReq,
+        QueryAlbumResp, QueryBlackUserResp, QueryCategory, QueryComment, QueryCommentReq,
+        QueryCommentResp, QueryFollowerResp, QueryManager, QueryRoleAuditResp, QueryTrackResp,
+        SharedTrackListResp, Track, UserPost,
     },
     user::Attribute,
 };
 use ic_ledger_types::{AccountIdentifier, Tokens, TransferArgs};
+use icrc_ledger_types::icrc1::account::Account;
 
 #[query(name = "__get_candid_interface_tmp_hack")]
 fn export_candid() -> String {
@@ -94,6 +94,17 @@ impl CanisterService {
     }
 }
 
+// User-specific methods
+impl CanisterService {
+    pub async fn update_followed_space(
+        &self,
+        user: Principal,
+        follow: bool,
+    ) -> CallResult<(Result<bool, String>,)> {
+        ic_cdk::call(self.principal, "update_followed_space", (user, follow)).await
+    }
+}
+
 // Indexer-specific methods
 impl CanisterService {
     pub async fn receive_message(&self, msg: Message) -> CallResult<(Result<String, String>,)> {
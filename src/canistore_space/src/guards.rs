@@ -1,4 +1,5 @@
 use candid::Principal;
+use canistore_types::space::ManagerRole;
 
 use crate::store;
 
@@ -20,6 +21,32 @@ pub fn write_guard() -> Result<(), String> {
     rest
 }
 
+#[inline(always)]
+pub fn editor_guard() -> Result<(), String> {
+    store::state::with(|s| s.role_permission(ic_cdk::caller(), ManagerRole::Editor))
+}
+
+#[inline(always)]
+pub fn finance_guard() -> Result<(), String> {
+    store::state::with(|s| s.role_permission(ic_cdk::caller(), ManagerRole::Finance))
+}
+
+#[inline(always)]
+pub fn moderator_guard() -> Result<(), String> {
+    store::state::with(|s| s.role_permission(ic_cdk::caller(), ManagerRole::Moderator))
+}
+
+#[inline(always)]
+pub fn publisher_guard() -> Result<(), String> {
+    store::state::with(|s| s.role_permission(ic_cdk::caller(), ManagerRole::Publisher))
+}
+
+#[inline(always)]
+pub fn read_guard() -> Result<(), String> {
+    let rest = store::state::with(|s| s.read_permission(ic_cdk::caller()));
+    rest
+}
+
 #[inline(always)]
 pub fn anonymous_guard() -> Result<(), String> {
     if ic_cdk::caller() == Principal::anonymous() {
@@ -28,3 +55,9 @@ pub fn anonymous_guard() -> Result<(), String> {
         Ok(())
     }
 }
+
+#[inline(always)]
+pub fn blacklist_guard() -> Result<(), String> {
+    anonymous_guard()?;
+    store::state::with(|s| s.blacklist_permission(ic_cdk::caller()))
+}
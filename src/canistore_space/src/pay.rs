@@ -1,14 +1,15 @@
 use candid::{Nat, Principal};
-use canistore_types::constant::CanisterType;
+use canistore_types::{constant::CanisterType, payment::TokenInfo};
 use ic_cdk::api::call::{call, RejectionCode};
 
 use crate::store::state;
-use crate::utils::new_subaccount;
+use crate::utils::{account_id, new_subaccount};
 use ic_ledger_types::{
     AccountIdentifier, Memo, Subaccount, Tokens, TransferArgs as ICPTransferArgs,
 };
 use icrc_ledger_types::icrc1::account::Account;
-use icrc_ledger_types::icrc1::transfer::TransferArg as ICRC1TransferArg;
+use icrc_ledger_types::icrc1::transfer::{TransferArg as ICRC1TransferArg, TransferError};
+use icrc_ledger_types::icrc2::transfer_from::{TransferFromArgs, TransferFromError};
 use std::convert::TryFrom;
 
 // Helper function to convert Nat to u64
@@ -21,81 +22,140 @@ fn nat_to_u64(nat: Nat) -> u64 {
     }
 }
 
-pub async fn token_balance(token: &str, account: Account) -> u64 {
+// Resolves the ledger canister of a builtin or registered token
+pub fn token_ledger(token: &str) -> Result<Principal, String> {
     let env = state::get_env();
-    match token {
-        "ICP" => {
-            let ledger_canister_id =
-                Principal::from_text(env.get_canister_pid(CanisterType::Ledger)).unwrap();
-            let amount: Result<(Nat,), _> =
-                call(ledger_canister_id, "icrc1_balance_of", (account,)).await;
-            match amount {
-                Ok((value,)) => nat_to_u64(value),
-                Err(_) => 0,
-            }
-        }
-        "CKBTC" => {
-            let ckbtc_ledger_canister_id =
-                Principal::from_text(env.get_canister_pid(CanisterType::CkBTCLedger)).unwrap();
-            let amount: Result<(Nat,), _> =
-                call(ckbtc_ledger_canister_id, "icrc1_balance_of", (account,)).await;
-            match amount {
-                Ok((value,)) => nat_to_u64(value),
-                Err(_) => 0,
+    let pid = match token {
+        "ICP" => env.get_canister_pid(CanisterType::Ledger),
+        "CKBTC" => env.get_canister_pid(CanisterType::CkBTCLedger),
+        _ => {
+            return match state::get_token(token) {
+                Some(info) if info.enabled => Ok(info.ledger_id),
+                _ => Err("Unsupported token".to_string()),
             }
         }
-        _ => 0,
+    };
+    Principal::from_text(pid).map_err(|_| format!("Invalid {} ledger canister id", token))
+}
+
+pub async fn token_balance(token: &str, account: Account) -> u64 {
+    let ledger_canister_id = match token_ledger(token) {
+        Ok(ledger_canister_id) => ledger_canister_id,
+        Err(_) => return 0,
+    };
+    let amount: Result<(Nat,), _> = call(ledger_canister_id, "icrc1_balance_of", (account,)).await;
+    match amount {
+        Ok((value,)) => nat_to_u64(value),
+        Err(_) => 0,
     }
 }
 
-// Function to handle ICRC1 transfers for ICP and CKBTC
+// Function to handle ICRC1 transfers for any accepted token
 pub async fn icrc1_transfer(
     token: &str,
     from: Option<[u8; 32]>,
     to: Account,
     amount: u64,
 ) -> Result<u64, String> {
-    let fee = token_fee(token);
+    let ledger_canister_id = token_ledger(token)?;
+    // The ledger applies its current fee
     let args = ICRC1TransferArg {
         to,
-        fee: Some(Nat::from(fee)),
+        fee: None,
         memo: None,
         from_subaccount: from,
         created_at_time: None,
         amount: Nat::from(amount),
     };
-    let env = state::get_env();
 
-    match token {
-        "ICP" => {
-            let ledger_canister_id =
-                Principal::from_text(env.get_canister_pid(CanisterType::Ledger)).unwrap();
-            let result: Result<(Nat,), (RejectionCode, String)> =
-                call(ledger_canister_id, "icrc1_transfer", (args,)).await;
-
-            match result {
-                Ok((height,)) => Ok(nat_to_u64(height)),
-                Err((rejection_code, err_msg)) => Err(format!(
-                    "Canister call failed: {:?}, {}",
-                    rejection_code, err_msg
-                )),
-            }
-        }
-        "CKBTC" => {
-            let ckbtc_ledger_canister_id =
-                Principal::from_text(env.get_canister_pid(CanisterType::CkBTCLedger)).unwrap();
-            let result: Result<(Nat,), (RejectionCode, String)> =
-                call(ckbtc_ledger_canister_id, "icrc1_transfer", (args,)).await;
-
-            match result {
-                Ok((height,)) => Ok(nat_to_u64(height)),
-                Err((rejection_code, err_msg)) => Err(format!(
-                    "Canister call failed: {:?}, {}",
-                    rejection_code, err_msg
-                )),
+    let result: Result<(Result<Nat, TransferError>,), (RejectionCode, String)> =
+        call(ledger_canister_id, "icrc1_transfer", (args,)).await;
+
+    match result {
+        Ok((Ok(height),)) => Ok(nat_to_u64(height)),
+        Ok((Err(err),)) => Err(format!("ledger transfer error {:?}", err)),
+        Err((rejection_code, err_msg)) => Err(format!(
+            "Canister call failed: {:?}, {}",
+            rejection_code, err_msg
+        )),
+    }
+}
+
+// Pulls funds approved by the owner of `from` with ICRC-2 transfer_from
+pub async fn icrc2_transfer_from(
+    token: &str,
+    from: Account,
+    to: Account,
+    amount: u64,
+) -> Result<u64, String> {
+    let ledger_canister_id = token_ledger(token)?;
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from,
+        to,
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+
+    let result: Result<(Result<Nat, TransferFromError>,), (RejectionCode, String)> =
+        call(ledger_canister_id, "icrc2_transfer_from", (args,)).await;
+
+    match result {
+        Ok((Ok(height),)) => Ok(nat_to_u64(height)),
+        Ok((Err(err),)) => Err(format!("ledger transfer_from error {:?}", err)),
+        Err((rejection_code, err_msg)) => Err(format!(
+            "Canister call failed: {:?}, {}",
+            rejection_code, err_msg
+        )),
+    }
+}
+
+// Fetches the metadata of an ICRC-1 ledger to register it as a token
+pub async fn fetch_token_info(ledger_id: Principal) -> Result<TokenInfo, String> {
+    let call_err =
+        |(code, msg): (RejectionCode, String)| format!("Canister call failed: {:?}, {}", code, msg);
+    let (symbol,): (String,) = call(ledger_id, "icrc1_symbol", ())
+        .await
+        .map_err(call_err)?;
+    let (decimals,): (u8,) = call(ledger_id, "icrc1_decimals", ())
+        .await
+        .map_err(call_err)?;
+    let fee = fetch_token_fee(ledger_id).await?;
+
+    Ok(TokenInfo {
+        symbol,
+        ledger_id,
+        decimals,
+        fee,
+        enabled: true,
+        updated: ic_cdk::api::time(),
+    })
+}
+
+pub async fn fetch_token_fee(ledger_id: Principal) -> Result<u64, String> {
+    let (fee,): (Nat,) = call(ledger_id, "icrc1_fee", ())
+        .await
+        .map_err(|(code, msg)| format!("Canister call failed: {:?}, {}", code, msg))?;
+    u64::try_from(fee.0).map_err(|_| "Token fee is too large".to_string())
+}
+
+// Stores the current fees of the builtin and registered ledgers
+pub async fn refresh_token_fees() {
+    for token in state::accepted_tokens() {
+        let fee = match token_ledger(&token) {
+            Ok(ledger_id) => fetch_token_fee(ledger_id).await,
+            Err(err) => Err(err),
+        };
+        match fee {
+            Ok(fee) => {
+                state::load();
+                state::set_token_fee(&token, fee, ic_cdk::api::time());
+                state::save();
             }
+            Err(err) => ic_cdk::print(format!("Failed to fetch the {} fee: {}", token, err)),
         }
-        _ => Err("Unsupported token".to_string()),
     }
 }
 
@@ -122,14 +182,34 @@ pub async fn transfer_icp(
     ic_ledger_types::transfer(ledger_canister_id, args)
         .await
         .map_err(|e| format!("failed to call ledger: {:?}", e))?
-        .map_err(|e| format!("ledger transfer error {:?}", e))
+        .map_err(|e| {
+            // The next transfer uses the fee the ledger expects
+            if let ic_ledger_types::TransferError::BadFee { expected_fee } = &e {
+                state::load();
+                state::set_token_fee("ICP", expected_fee.e8s(), ic_cdk::api::time());
+                state::save();
+            }
+            format!("ledger transfer error {:?}", e)
+        })
 }
 
+// Fees of the builtin ledgers until icrc1_fee has been fetched
+const ICP_DEFAULT_FEE: u64 = 10_000;
+const CKBTC_DEFAULT_FEE: u64 = 10;
+
 pub fn token_fee(token: &str) -> u64 {
     match token {
-        "ICP" => 10_000, // Example: fee for ICP
-        "CKBTC" => 10,   // Example: fee for CKBTC
-        _ => 0,
+        "ICP" => state::get_builtin_token_fee(token).unwrap_or(ICP_DEFAULT_FEE),
+        "CKBTC" => state::get_builtin_token_fee(token).unwrap_or(CKBTC_DEFAULT_FEE),
+        _ => state::get_token(token).map_or(0, |info| info.fee),
+    }
+}
+
+// Encodes the default account of a principal as expected by `token_transfer`
+pub fn token_recipient(token: &str, owner: Principal) -> Vec<u8> {
+    match token {
+        "ICP" => account_id(owner, None).as_ref().to_vec(),
+        _ => owner.as_slice().to_vec(),
     }
 }
 
@@ -147,13 +227,28 @@ pub async fn token_transfer(
             let from_subaccount = new_subaccount(from);
             transfer_icp(from_subaccount, to_account, amount).await
         }
-        "CKBTC" => {
+        _ => {
             let account = Account {
                 owner: Principal::from_slice(&to),
                 subaccount: None,
             };
             icrc1_transfer(token, from, account, amount).await
         }
-        _ => Err("Unsupported token".to_string()),
+    }
+}
+
+// Transfers to an ICRC-1 account, ICP is sent to the matching account identifier
+pub async fn account_transfer(
+    token: &str,
+    from: Option<[u8; 32]>,
+    to: Account,
+    amount: u64,
+) -> Result<u64, String> {
+    match token {
+        "ICP" => {
+            let to_account = account_id(to.owner, to.subaccount);
+            transfer_icp(new_subaccount(from), to_account, amount).await
+        }
+        _ => icrc1_transfer(token, from, to, amount).await,
     }
 }
@@ -5,8 +5,14 @@ use canistore_types::{
     error::{CustomError, ErrorCode},
     license::{License, LicenseRecord},
     message::{Message, MessageSource, MessageType},
-    payment::{PaymentOrder, PaymentType, SubscriberInfo},
-    space::{Album, Category, SharedTrack, Track, UserPost},
+    payment::{
+        AwardOrder, JournalEntry, PaymentOrder, PaymentType, PayoutRecord, Subscriber,
+        SubscriberInfo, TokenInfo,
+    },
+    space::{
+        Album, BlacklistedUser, Category, Comment, ManagerRole, RoleAuditEntry, SharedTrack, Track,
+        UserPost,
+    },
 };
 use ciborium::{from_reader, into_writer};
 use ic_cdk_timers::TimerId;
@@ -53,6 +59,7 @@ pub struct Space {
     pub created: u64,
     pub sub_prices: Vec<u64>,
     pub subscribers: Vec<SubscriberInfo>,
+    // Legacy list, moved to the follower stores on upgrade.
     pub followers: Vec<Principal>,
     pub total_subscribers: u64,
     pub total_albums: u64,
@@ -70,6 +77,22 @@ pub struct Space {
     pub services: Vec<String>,
     pub store_track_ids: Vec<u64>,
     pub env: Environment,
+    #[serde(default)]
+    pub next_comment_id: u32,
+    #[serde(default)]
+    pub blacklist: Vec<BlacklistedUser>,
+    #[serde(default)]
+    pub next_category_id: u32,
+    #[serde(default)]
+    pub next_record_id: u64,
+    #[serde(default)]
+    pub tokens: Vec<TokenInfo>,
+    #[serde(default)]
+    pub manager_roles: BTreeMap<Principal, Vec<ManagerRole>>, // managers without an entry hold the legacy roles
+    #[serde(default)]
+    pub token_income: Vec<(String, u64)>, // paid orders, per token
+    #[serde(default)]
+    pub builtin_token_fees: BTreeMap<String, u64>, // fetched from icrc1_fee of the builtin ledgers
 }
 
 impl Default for Space {
@@ -118,6 +141,14 @@ impl Default for Space {
             services: vec![],
             store_track_ids: vec![],
             env: Environment::Test,
+            next_comment_id: 0,
+            blacklist: vec![],
+            next_category_id: 0,
+            next_record_id: 0,
+            tokens: vec![],
+            manager_roles: BTreeMap::new(),
+            token_income: vec![],
+            builtin_token_fees: BTreeMap::new(),
         }
     }
 }
@@ -131,6 +162,24 @@ impl Space {
         }
     }
 
+    pub fn manager_roles(&self, pid: &Principal) -> Vec<ManagerRole> {
+        if !self.managers.contains(pid) {
+            return vec![];
+        }
+        self.manager_roles
+            .get(pid)
+            .cloned()
+            .unwrap_or_else(ManagerRole::legacy)
+    }
+
+    pub fn role_permission(&self, caller: Principal, role: ManagerRole) -> Result<(), String> {
+        if caller == self.owner || self.manager_roles(&caller).contains(&role) {
+            Ok(())
+        } else {
+            Err("Unauthorized".to_string())
+        }
+    }
+
     pub fn owner_permission(&self, caller: Principal) -> Result<(), String> {
         if caller == self.owner {
             Ok(())
@@ -139,6 +188,27 @@ impl Space {
         }
     }
 
+    pub fn is_blacklisted(&self, caller: &Principal) -> bool {
+        self.blacklist.iter().any(|user| &user.pid == caller)
+    }
+
+    // Private spaces are hidden from anonymous callers
+    pub fn read_permission(&self, caller: Principal) -> Result<(), String> {
+        if self.visibility == 0 && caller == Principal::anonymous() {
+            Err("Unauthorized".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn blacklist_permission(&self, caller: Principal) -> Result<(), String> {
+        if self.is_blacklisted(&caller) {
+            Err("Blacklisted".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn controller_or_owner_permission(&self, caller: Principal) -> Result<(), String> {
         if caller == self.owner || ic_cdk::api::is_controller(&caller) {
             Ok(())
@@ -175,6 +245,7 @@ pub struct SpaceInfo {
     pub total_tracks: u64,
     pub total_licenses: u64,
     pub total_income: u64,
+    pub token_income: Vec<(String, u64)>,
     pub total_orders: u64,
     pub total_followers: u64,
     pub total_view: u64,
@@ -212,12 +283,14 @@ impl SpaceInfo {
             created: space.created,
             sub_prices: space.sub_prices.clone(),
             subscribers: space.subscribers.clone(),
-            followers: space.followers.clone(),
+            // Followers are paged by get_followers.
+            followers: vec![],
             total_subscribers: space.total_subscribers,
             total_albums: space.total_albums,
             total_tracks: space.total_tracks,
             total_licenses: space.total_licenses,
             total_income: space.total_income,
+            token_income: space.token_income.clone(),
             total_orders: space.total_orders,
             total_followers: space.total_followers,
             total_view: space.total_view,
@@ -412,6 +485,111 @@ impl Storable for MessageWrapper {
     }
 }
 
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct CommentWrapper(pub Comment);
+
+impl Storable for CommentWrapper {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+}
+
+impl CommentWrapper {
+    pub fn into_inner(self) -> Comment {
+        self.0
+    }
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct PayoutRecordWrapper(pub PayoutRecord);
+
+impl Storable for PayoutRecordWrapper {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+}
+
+impl PayoutRecordWrapper {
+    pub fn into_inner(self) -> PayoutRecord {
+        self.0
+    }
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct AwardOrderWrapper(pub AwardOrder);
+
+impl Storable for AwardOrderWrapper {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+}
+
+impl AwardOrderWrapper {
+    pub fn into_inner(self) -> AwardOrder {
+        self.0
+    }
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct JournalEntryWrapper(pub JournalEntry);
+
+impl Storable for JournalEntryWrapper {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+}
+
+impl JournalEntryWrapper {
+    pub fn into_inner(self) -> JournalEntry {
+        self.0
+    }
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct RoleAuditEntryWrapper(pub RoleAuditEntry);
+
+impl Storable for RoleAuditEntryWrapper {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+}
+
+impl RoleAuditEntryWrapper {
+    pub fn into_inner(self) -> RoleAuditEntry {
+        self.0
+    }
+}
+
 const SPACE_MEMORY_ID: MemoryId = MemoryId::new(0);
 const ALBUM_MEMORY_ID: MemoryId = MemoryId::new(1);
 const TRACK_MEMORY_ID: MemoryId = MemoryId::new(2);
@@ -421,6 +599,14 @@ const PAYMENT_MEMORY_ID: MemoryId = MemoryId::new(5);
 const SHARE_MEMORY_ID: MemoryId = MemoryId::new(6);
 const POST_MEMORY_ID: MemoryId = MemoryId::new(7);
 const MESSAGE_MEMORY_ID: MemoryId = MemoryId::new(8);
+const COMMENT_MEMORY_ID: MemoryId = MemoryId::new(9);
+const PAYOUT_MEMORY_ID: MemoryId = MemoryId::new(10);
+const AWARD_MEMORY_ID: MemoryId = MemoryId::new(11);
+const JOURNAL_MEMORY_ID: MemoryId = MemoryId::new(12);
+const ROLE_AUDIT_MEMORY_ID: MemoryId = MemoryId::new(13);
+const FOLLOWER_MEMORY_ID: MemoryId = MemoryId::new(14);
+const FOLLOWER_SEQ_MEMORY_ID: MemoryId = MemoryId::new(15);
+const COMMENT_LIKE_MEMORY_ID: MemoryId = MemoryId::new(16);
 
 thread_local! {
     static SPACE: RefCell<Space> = RefCell::new(Space::default());
@@ -483,11 +669,76 @@ thread_local! {
         )
     );
 
+    // (album_id, comment_id) -> comment
+    static COMMENT_STORE: RefCell<StableBTreeMap<(u64, u32), CommentWrapper, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(COMMENT_MEMORY_ID)),
+        )
+    );
+
+    // (order_id, index in the split sheet) -> payout
+    static PAYOUT_STORE: RefCell<StableBTreeMap<(u64, u32), PayoutRecordWrapper, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(PAYOUT_MEMORY_ID)),
+        )
+    );
+
+    // order_id -> confirmed award
+    static AWARD_STORE: RefCell<StableBTreeMap<u64, AwardOrderWrapper, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(AWARD_MEMORY_ID)),
+        )
+    );
+
+    static JOURNAL_STORE: RefCell<StableBTreeMap<u64, JournalEntryWrapper, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(JOURNAL_MEMORY_ID)),
+        )
+    );
+
+    static ROLE_AUDIT_STORE: RefCell<StableBTreeMap<u64, RoleAuditEntryWrapper, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(ROLE_AUDIT_MEMORY_ID)),
+        )
+    );
+
+    // follower -> sequence number of the follow
+    static FOLLOWER_STORE: RefCell<StableBTreeMap<Principal, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(FOLLOWER_MEMORY_ID)),
+        )
+    );
+
+    // sequence number -> follower, lists the followers in follow order
+    static FOLLOWER_SEQ_STORE: RefCell<StableBTreeMap<u64, Principal, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(FOLLOWER_SEQ_MEMORY_ID)),
+        )
+    );
+
+    // ((album id, comment id), user) -> (), the users who liked a comment
+    static COMMENT_LIKE_STORE: RefCell<StableBTreeMap<((u64, u32), Principal), (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(COMMENT_LIKE_MEMORY_ID)),
+        )
+    );
+
+    // users whose follow change waits for the user canister
+    static FOLLOW_LOCKS: RefCell<BTreeSet<Principal>> = RefCell::new(BTreeSet::new());
+
     pub static TIMER_IDS: RefCell<Vec<TimerId>> = RefCell::new(Vec::new());
+
+    static RELEASE_TIMER: RefCell<Option<TimerId>> = RefCell::new(None);
 }
 
 pub mod state {
     use super::*;
+    use crate::utils::check_page_size;
+    use canistore_types::payment::{
+        QueryCommonReq, QuerySort, BUILTIN_TOKENS, MAX_SPACE_TOKENS,
+        TOKEN_FEE_REFRESH_INTERVAL_SECS,
+    };
+    use canistore_types::space::QueryManager;
 
     pub fn with<R>(f: impl FnOnce(&Space) -> R) -> R {
         SPACE.with(|r| f(&r.borrow()))
@@ -516,15 +767,58 @@ pub mod state {
         });
     }
 
-    pub fn add_managers(new_managers: Vec<Principal>) {
+    // Grants exactly the given roles, an empty list removes the manager.
+    pub fn set_manager_roles(pid: Principal, mut roles: Vec<ManagerRole>) {
+        roles.sort();
+        roles.dedup();
         SPACE.with(|r| {
             let mut space = r.borrow_mut();
-            for manager in new_managers {
-                space.managers.insert(manager);
+            if roles.is_empty() {
+                space.managers.remove(&pid);
+                space.manager_roles.remove(&pid);
+            } else {
+                space.managers.insert(pid);
+                space.manager_roles.insert(pid, roles);
             }
         });
     }
 
+    // Followers used to be kept in the space state, they now live in the follower stores.
+    pub fn take_legacy_followers() -> Vec<Principal> {
+        SPACE.with(|r| std::mem::take(&mut r.borrow_mut().followers))
+    }
+
+    // Gives managers from before roles existed an explicit least privileged entry.
+    pub fn migrate_manager_roles() -> Vec<Principal> {
+        SPACE.with(|r| {
+            let mut space = r.borrow_mut();
+            let legacy: Vec<Principal> = space
+                .managers
+                .iter()
+                .filter(|pid| !space.manager_roles.contains_key(pid))
+                .cloned()
+                .collect();
+            for pid in &legacy {
+                space.manager_roles.insert(*pid, ManagerRole::legacy());
+            }
+            legacy
+        })
+    }
+
+    pub fn get_managers() -> Vec<QueryManager> {
+        SPACE.with(|r| {
+            let space = r.borrow();
+            space
+                .managers
+                .iter()
+                .map(|pid| QueryManager {
+                    pid: *pid,
+                    roles: space.manager_roles(pid),
+                })
+                .collect()
+        })
+    }
+
     pub fn get_is_share_store(track_id: u64) -> bool {
         SPACE.with(|r| r.borrow().store_track_ids.contains(&track_id))
     }
@@ -545,10 +839,138 @@ pub mod state {
     pub fn get_env() -> Environment {
         SPACE.with(|r| r.borrow().env.clone())
     }
+
+    pub fn get_token(symbol: &str) -> Option<TokenInfo> {
+        SPACE.with(|r| {
+            r.borrow()
+                .tokens
+                .iter()
+                .find(|token| token.symbol == symbol)
+                .cloned()
+        })
+    }
+
+    // Builtin tokens are always accepted, registered tokens only while enabled.
+    pub fn is_token_accepted(symbol: &str) -> bool {
+        BUILTIN_TOKENS.contains(&symbol) || get_token(symbol).map_or(false, |token| token.enabled)
+    }
+
+    pub fn accepted_tokens() -> Vec<String> {
+        SPACE.with(|r| {
+            BUILTIN_TOKENS
+                .iter()
+                .map(|token| token.to_string())
+                .chain(
+                    r.borrow()
+                        .tokens
+                        .iter()
+                        .filter(|token| token.enabled)
+                        .map(|token| token.symbol.clone()),
+                )
+                .collect()
+        })
+    }
+
+    pub fn get_builtin_token_fee(symbol: &str) -> Option<u64> {
+        SPACE.with(|r| r.borrow().builtin_token_fees.get(symbol).copied())
+    }
+
+    pub fn set_token_fee(symbol: &str, fee: u64, now: u64) {
+        with_mut(|space| {
+            if BUILTIN_TOKENS.contains(&symbol) {
+                space.builtin_token_fees.insert(symbol.to_string(), fee);
+            } else if let Some(token) = space.tokens.iter_mut().find(|t| t.symbol == symbol) {
+                token.fee = fee;
+                token.updated = now;
+            }
+        });
+    }
+
+    // Ledger fees can change, so they are fetched again at startup and then periodically.
+    pub fn set_token_fee_refresh_timer() {
+        let refresh = || ic_cdk::spawn(crate::pay::refresh_token_fees());
+        ic_cdk_timers::set_timer(Duration::ZERO, refresh);
+        let timer_id = ic_cdk_timers::set_timer_interval(
+            Duration::from_secs(TOKEN_FEE_REFRESH_INTERVAL_SECS),
+            refresh,
+        );
+        TIMER_IDS.with(|timer_ids| timer_ids.borrow_mut().push(timer_id));
+    }
+
+    pub fn set_token(token: TokenInfo) -> Result<(), String> {
+        SPACE.with(|r| {
+            let mut space = r.borrow_mut();
+            match space.tokens.iter_mut().find(|t| t.symbol == token.symbol) {
+                Some(existing) => *existing = token,
+                None => {
+                    if space.tokens.len() >= MAX_SPACE_TOKENS {
+                        return Err(
+                            CustomError::new(ErrorCode::MaximumRecords, Some("tokens")).to_string()
+                        );
+                    }
+                    space.tokens.push(token);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // Blocks the users and marks their album subscriptions.
+    pub fn add_blacklist(pids: Vec<Principal>, now: u64) -> Result<(), String> {
+        SPACE.with(|r| {
+            let mut space = r.borrow_mut();
+            if pids.iter().any(|pid| space.write_permission(*pid).is_ok()) {
+                return Err(
+                    CustomError::new(ErrorCode::OperationNotAllowed, Some("managers")).to_string(),
+                );
+            }
+            for pid in pids.iter() {
+                if !space.is_blacklisted(pid) {
+                    space.blacklist.push(BlacklistedUser {
+                        pid: *pid,
+                        created: now,
+                    });
+                }
+            }
+            Ok(())
+        })?;
+
+        album::set_subscribers_black(&pids, true);
+        Ok(())
+    }
+
+    pub fn remove_blacklist(pids: Vec<Principal>) {
+        SPACE.with(|r| {
+            r.borrow_mut()
+                .blacklist
+                .retain(|user| !pids.contains(&user.pid))
+        });
+
+        album::set_subscribers_black(&pids, false);
+    }
+
+    pub fn limit_blacklist(req: &QueryCommonReq) -> (usize, bool, Vec<BlacklistedUser>) {
+        let (page, size) = check_page_size(req.page, req.size);
+        let start = (page - 1) * size;
+
+        SPACE.with(|r| {
+            let space = r.borrow();
+            let total = space.blacklist.len();
+            let mut data: Vec<BlacklistedUser> = space.blacklist.clone();
+            if let QuerySort::TimeDesc = req.sort {
+                data.reverse();
+            }
+            let data = data.into_iter().skip(start).take(size).collect();
+            (total, total > start + size, data)
+        })
+    }
 }
 
 pub mod album {
-    use canistore_types::space::AlbumListEntry;
+    use crate::utils::check_page_size;
+    use canistore_types::space::{
+        AlbumListEntry, AlbumStat, AlbumStatus, QueryAlbum, QueryAlbumReq, QuerySort, SortedAlbum,
+    };
 
     use super::*;
 
@@ -560,6 +982,37 @@ pub mod album {
         ALBUM_STORE.with(|r| r.borrow().get(&id))
     }
 
+    #[derive(PartialEq, Eq)]
+    pub enum AlbumAccess {
+        Full,
+        Metadata, // the album can be seen but its tracks are gated
+        Hidden,
+    }
+
+    // Drafts, private and deleted albums are only visible to managers,
+    // subscription albums expose their tracks to active subscribers.
+    pub fn album_access(album: &Album, caller: Principal, is_manager: bool) -> AlbumAccess {
+        if is_manager {
+            return AlbumAccess::Full;
+        }
+        match album.status {
+            AlbumStatus::Public => AlbumAccess::Full,
+            AlbumStatus::Subscription => {
+                let now = ic_cdk::api::time();
+                if album
+                    .subscribers
+                    .iter()
+                    .any(|subscriber| subscriber.pid == caller && subscriber.is_active(now))
+                {
+                    AlbumAccess::Full
+                } else {
+                    AlbumAccess::Metadata
+                }
+            }
+            AlbumStatus::Draft | AlbumStatus::Private | AlbumStatus::Deleted => AlbumAccess::Hidden,
+        }
+    }
+
     pub fn add_album(id: u64, album: Album) {
         ALBUM_STORE.with(|r| r.borrow_mut().insert(id, AlbumWrapper(album)));
     }
@@ -582,11 +1035,18 @@ pub mod album {
         })
     }
 
-    pub fn get_albums_list(limit: usize, offset: usize) -> Vec<AlbumListEntry> {
+    pub fn get_albums_list(limit: usize, offset: usize, is_manager: bool) -> Vec<AlbumListEntry> {
         ALBUM_STORE.with(|r| {
             let mut albums: Vec<AlbumListEntry> = r
                 .borrow()
                 .iter()
+                .filter(|(_, wrapper)| {
+                    is_manager
+                        || matches!(
+                            wrapper.0.status,
+                            AlbumStatus::Public | AlbumStatus::Subscription
+                        )
+                })
                 .map(|(_, wrapper)| AlbumListEntry::from(&wrapper.0))
                 .collect();
 
@@ -598,6 +1058,58 @@ pub mod album {
         })
     }
 
+    // Pinned albums come first, the rest follow the requested time order.
+    // Drafts, private and deleted albums are only listed for managers.
+    pub fn query_albums(
+        req: &QueryAlbumReq,
+        is_manager: bool,
+    ) -> (usize, bool, AlbumStat, Vec<QueryAlbum>) {
+        let (page, size) = check_page_size(req.page as usize, req.size as usize);
+        let start = (page - 1) * size;
+        let mut stat = AlbumStat::default();
+
+        let mut albums: Vec<(SortedAlbum, Album)> = ALBUM_STORE.with(|r| {
+            r.borrow()
+                .iter()
+                .map(|(_, wrapper)| wrapper.into_inner())
+                .filter(|album| {
+                    is_manager
+                        || matches!(
+                            album.status,
+                            AlbumStatus::Public | AlbumStatus::Subscription
+                        )
+                })
+                .filter(|album| req.matches(album))
+                .filter(|album| {
+                    stat.add(&album.status);
+                    match &req.status {
+                        Some(status) => &album.status == status,
+                        None => album.status != AlbumStatus::Deleted,
+                    }
+                })
+                .map(|album| (SortedAlbum::from(&album), album))
+                .collect()
+        });
+
+        albums.sort_by(|(a, _), (b, _)| match req.sort {
+            QuerySort::TimeDesc => SortedAlbum::compare_albums_desc(a, b),
+            QuerySort::TimeAsc => b
+                .toped
+                .cmp(&a.toped)
+                .then_with(|| a.created.cmp(&b.created)),
+        });
+
+        let total = albums.len();
+        let data = albums
+            .iter()
+            .skip(start)
+            .take(size)
+            .map(|(_, album)| QueryAlbum::from(album))
+            .collect();
+
+        (total, total > start + size, stat, data)
+    }
+
     pub fn add_track_ids_to_album(album_id: u64, track_ids: Vec<u64>) -> Result<(), String> {
         ALBUM_STORE.with(|r| {
             let mut store = r.borrow_mut();
@@ -619,14 +1131,79 @@ pub mod album {
         })
     }
 
-    pub fn remove_track_ids_from_album(album_id: u64, track_ids: Vec<u64>) -> Result<(), String> {
+    pub fn get_subscriber(album_id: u64, pid: Principal) -> Option<SubscriberInfo> {
+        get_album(album_id).and_then(|album| {
+            album
+                .into_inner()
+                .subscribers
+                .into_iter()
+                .find(|subscriber| subscriber.pid == pid)
+        })
+    }
+
+    // Records a new subscriber or extends the existing subscription of the same user.
+    pub fn add_subscriber(album_id: u64, subscriber: Subscriber) -> Result<SubscriberInfo, String> {
         ALBUM_STORE.with(|r| {
             let mut store = r.borrow_mut();
+            let mut album = match store.get(&album_id) {
+                Some(wrapper) => wrapper.into_inner(),
+                None => return Err(format!("Album with ID {} not found", album_id)),
+            };
 
-            if let Some(album_wrapper) = store.get(&album_id) {
-                let mut album = album_wrapper.clone();
-
-                album.0.track_ids.retain(|id| !track_ids.contains(id));
+            let info = match album
+                .subscribers
+                .iter_mut()
+                .find(|info| info.pid == subscriber.pid)
+            {
+                Some(info) => {
+                    info.extend(&subscriber);
+                    info.clone()
+                }
+                None => {
+                    let info = SubscriberInfo::new(subscriber);
+                    album.subscribers.push(info.clone());
+                    album.subscriber_count += 1;
+                    info
+                }
+            };
+
+            store.insert(album_id, AlbumWrapper(album));
+            Ok(info)
+        })
+    }
+
+    pub fn set_subscribers_black(pids: &[Principal], is_black: bool) {
+        ALBUM_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let updated: Vec<(u64, Album)> = store
+                .iter()
+                .filter_map(|(id, wrapper)| {
+                    let mut album = wrapper.into_inner();
+                    let mut changed = false;
+                    for subscriber in album.subscribers.iter_mut() {
+                        if pids.contains(&subscriber.pid) && subscriber.is_black != is_black {
+                            subscriber.is_black = is_black;
+                            changed = true;
+                        }
+                    }
+                    changed.then_some((id, album))
+                })
+                .collect();
+
+            for (id, album) in updated {
+                store.insert(id, AlbumWrapper(album));
+            }
+        });
+    }
+
+    pub fn remove_track_ids_from_album(album_id: u64, track_ids: Vec<u64>) -> Result<(), String> {
+        ALBUM_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+
+            if let Some(album_wrapper) = store.get(&album_id) {
+                let mut album = album_wrapper.clone();
+
+                album.0.track_ids.retain(|id| !track_ids.contains(id));
                 store.insert(album_id, album);
                 Ok(())
             } else {
@@ -794,11 +1371,14 @@ pub mod track {
 
 pub mod license {
     use canistore_types::license::{
-        AssetType, ChannelType, LicenseKey, LicenseListEntry, LicensedMedia, LicensedTerritory,
-        RightPeriod, UsageRights,
+        AssetType, ChannelType, LicenseKey, LicenseListEntry, LicenseUsageReport, LicensedMedia,
+        LicensedTerritory, RightPeriod, TrackUsageReport, UsageRights,
     };
+    use canistore_types::payment::{QueryCommonReq, QuerySort};
     use ic_cdk::api::time;
 
+    use crate::utils::check_page_size;
+
     use crate::SYSTEM_LICENSE_USER;
 
     use super::*;
@@ -812,9 +1392,64 @@ pub mod license {
         LICENSE_STORE.with(|r| r.borrow().get(&id))
     }
 
+    // Only the licenses that are still active are returned.
     pub fn get_license_by_track(track_id: u64) -> Option<LicenseMap> {
         let license_key = LicenseKey::new(None, Some(track_id));
+        let now = time();
+        get_license(license_key.to_tuple())
+            .map(|license_map| {
+                LicenseMap(
+                    license_map
+                        .0
+                        .into_iter()
+                        .filter(|(_, license)| license.is_active(now))
+                        .collect(),
+                )
+            })
+            .filter(|license_map| !license_map.0.is_empty())
+    }
+
+    // Only the licenses that are still active are returned.
+    pub fn get_license_by_album(album_id: u64) -> Option<LicenseMap> {
+        let license_key = LicenseKey::new(Some(album_id), None);
+        let now = time();
         get_license(license_key.to_tuple())
+            .map(|license_map| {
+                LicenseMap(
+                    license_map
+                        .0
+                        .into_iter()
+                        .filter(|(_, license)| license.is_active(now))
+                        .collect(),
+                )
+            })
+            .filter(|license_map| !license_map.0.is_empty())
+    }
+
+    pub fn get_album_genesis_license(album_id: u64) -> Option<License> {
+        get_license_by_album(album_id)
+            .and_then(|license_map| license_map.0.get(&ic_cdk::id()).cloned())
+    }
+
+    // A track is licensed either directly or through a license of its album.
+    pub fn is_track_licensed(track_id: u64) -> bool {
+        get_license_by_track(track_id).is_some()
+            || track::get_track(track_id)
+                .and_then(|track| track.into_inner().album_id)
+                .map_or(false, |album_id| get_license_by_album(album_id).is_some())
+    }
+
+    // Returns the active license of the user for the track and whether it was granted by the album.
+    pub fn find_track_license(track_id: u64, user_pid: &Principal) -> Option<(License, bool)> {
+        if let Some(license) = get_license_by_track(track_id)
+            .and_then(|license_map| license_map.get(user_pid).cloned())
+        {
+            return Some((license, false));
+        }
+
+        let album_id = track::get_track(track_id)?.into_inner().album_id?;
+        let license = get_license_by_album(album_id)?.get(user_pid).cloned()?;
+        Some((license, true))
     }
 
     pub fn get_track_genesis_license(track_id: u64) -> Option<License> {
@@ -825,10 +1460,150 @@ pub mod license {
         }
     }
 
+    pub fn find_license(license_id: u64) -> Option<License> {
+        LICENSE_STORE.with(|r| {
+            r.borrow().iter().find_map(|(_, license_map)| {
+                license_map
+                    .0
+                    .into_values()
+                    .find(|license| license.id == license_id)
+            })
+        })
+    }
+
+    pub fn update_license<R>(
+        license_id: u64,
+        f: impl FnOnce(&mut License) -> Result<R, String>,
+    ) -> Result<R, String> {
+        LICENSE_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let found = store.iter().find_map(|(key, license_map)| {
+                license_map
+                    .0
+                    .iter()
+                    .find(|(_, license)| license.id == license_id)
+                    .map(|(user, _)| (key, *user, license_map.clone()))
+            });
+            let (key, user, mut license_map) =
+                found.ok_or(format!("License with ID {} not found", license_id))?;
+
+            let license = license_map.0.get_mut(&user).unwrap();
+            let result = f(license)?;
+            store.insert(key, license_map);
+            Ok(result)
+        })
+    }
+
+    pub fn revoke_license(license_id: u64, now: u64) -> Result<(), String> {
+        update_license(license_id, |license| {
+            if license.user == ic_cdk::id() {
+                return Err("Genesis license cannot be revoked".to_string());
+            }
+            if license.revoke_time.is_some() {
+                return Err("License has already been revoked".to_string());
+            }
+            license.revoke_time = Some(now);
+            Ok(())
+        })
+    }
+
+    // Marks the licenses whose right period has lapsed, returns the number marked.
+    pub fn sweep_expired_licenses(now: u64) -> usize {
+        LICENSE_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let lapsed: Vec<((u64, u64), LicenseMap, usize)> = store
+                .iter()
+                .filter_map(|(key, mut license_map)| {
+                    let mut marked = 0;
+                    for license in license_map.0.values_mut() {
+                        if license.expired_time.is_none() && license.revoke_time.is_none() {
+                            if let Some(expire_time) = license.expire_time() {
+                                if expire_time <= now {
+                                    license.expired_time = Some(expire_time);
+                                    marked += 1;
+                                }
+                            }
+                        }
+                    }
+                    (marked > 0).then_some((key, license_map, marked))
+                })
+                .collect();
+
+            let mut count = 0;
+            for (key, license_map, marked) in lapsed {
+                count += marked;
+                store.insert(key, license_map);
+            }
+            count
+        })
+    }
+
+    pub fn set_license_expiry_timer() {
+        let timer_id = ic_cdk_timers::set_timer_interval(Duration::from_secs(3600), || {
+            let count = sweep_expired_licenses(time());
+            if count > 0 {
+                ic_cdk::print(format!("Marked {} expired licenses", count));
+            }
+        });
+        TIMER_IDS.with(|timer_ids| timer_ids.borrow_mut().push(timer_id));
+    }
+
+    pub fn add_license_record(record: LicenseRecord) {
+        RECORD_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let mut records = store.get(&record.license_id).unwrap_or_default();
+            records.0.insert(record.id as u128, record.clone());
+            store.insert(record.license_id, records);
+        });
+    }
+
+    pub fn get_license_records(license_id: u64) -> Vec<LicenseRecord> {
+        RECORD_STORE.with(|r| {
+            r.borrow()
+                .get(&license_id)
+                .map(|records| records.0.into_values().collect())
+                .unwrap_or_default()
+        })
+    }
+
+    pub fn limit_license_records(
+        license_id: u64,
+        req: &QueryCommonReq,
+    ) -> (usize, bool, Vec<LicenseRecord>) {
+        let (page, size) = check_page_size(req.page, req.size);
+        let start = (page - 1) * size;
+
+        let mut records = get_license_records(license_id);
+        if let QuerySort::TimeDesc = req.sort {
+            records.reverse();
+        }
+
+        let total = records.len();
+        let data = records.into_iter().skip(start).take(size).collect();
+        (total, total > start + size, data)
+    }
+
+    pub fn get_track_usage_report(track_id: u64) -> TrackUsageReport {
+        let licenses = get_license_by_track(track_id)
+            .map(|license_map| {
+                license_map
+                    .0
+                    .values()
+                    .map(|license| {
+                        LicenseUsageReport::new(license, &get_license_records(license.id))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        TrackUsageReport::new(track_id, licenses)
+    }
+
+    // Licenses whose resource the caller cannot see are left out before paging.
     pub fn get_license_list(
         limit: usize,
         offset: usize,
         filter: Option<fn(&License) -> bool>,
+        visible: &dyn Fn(&LicenseKey) -> bool,
     ) -> Vec<LicenseListEntry> {
         LICENSE_STORE.with(|r| {
             let store = r.borrow();
@@ -843,6 +1618,7 @@ pub mod license {
                             Some(filter_fn) => filter_fn(license),
                             None => true,
                         })
+                        .filter(|license| visible(&license.resource_key))
                         .map(LicenseListEntry::from)
                         .collect::<Vec<_>>()
                 })
@@ -856,24 +1632,37 @@ pub mod license {
         })
     }
 
-    pub fn get_all_licenses(limit: usize, offset: usize) -> Vec<LicenseListEntry> {
-        get_license_list(limit, offset, None)
+    pub fn get_all_licenses(
+        limit: usize,
+        offset: usize,
+        visible: &dyn Fn(&LicenseKey) -> bool,
+    ) -> Vec<LicenseListEntry> {
+        get_license_list(limit, offset, None, visible)
     }
 
-    pub fn get_track_license_list(limit: usize, offset: usize) -> Vec<LicenseListEntry> {
+    pub fn get_track_license_list(
+        limit: usize,
+        offset: usize,
+        visible: &dyn Fn(&LicenseKey) -> bool,
+    ) -> Vec<LicenseListEntry> {
         get_license_list(
             limit,
             offset,
             Some(|license| license.resource_key.album_id.unwrap_or(0) == 0),
+            visible,
         )
     }
 
-    #[allow(dead_code)]
-    pub fn get_album_license_list(limit: usize, offset: usize) -> Vec<LicenseListEntry> {
+    pub fn get_album_license_list(
+        limit: usize,
+        offset: usize,
+        visible: &dyn Fn(&LicenseKey) -> bool,
+    ) -> Vec<LicenseListEntry> {
         get_license_list(
             limit,
             offset,
             Some(|license| license.resource_key.track_id.unwrap_or(0) == 0),
+            visible,
         )
     }
 
@@ -923,7 +1712,7 @@ pub mod license {
         licensed_territory: Vec<LicensedTerritory>,
         right_period: Vec<RightPeriod>,
         fee: Option<u128>,
-    ) {
+    ) -> bool {
         // Create a license key based on whether it's a track or an album
         let license_key = LicenseKey::new(album_id, track_id);
 
@@ -935,14 +1724,24 @@ pub mod license {
                 LicenseMap::new()
             };
 
-        // Only add a new license if the user does not already have one for this key
-        if !license_map.0.contains_key(&user_pid) {
+        // Only add a new license if the user does not already have an active one for this key,
+        // a revoked or lapsed license is replaced
+        let current_time = time();
+        let is_held = license_map
+            .0
+            .get(&user_pid)
+            .is_some_and(|license| license.is_active(current_time));
+        if !is_held {
             let new_license = License {
                 id,
                 user: user_pid,
                 resource_key: license_key.clone(),
                 start_time: time(),
-                valid_duration: None, // None
+                valid_duration: if user_pid == ic_cdk::id() || user_pid == SYSTEM_LICENSE_USER {
+                    None // genesis and platform licenses never expire
+                } else {
+                    RightPeriod::valid_duration(&right_period)
+                },
                 revoke_time: None,
                 channel,
                 asset_type,
@@ -952,6 +1751,8 @@ pub mod license {
                 right_period,
                 fee,
                 created: time(),
+                expired_time: None,
+                token_prices: None,
             };
 
             // Insert the new license into the map
@@ -959,11 +1760,22 @@ pub mod license {
 
             // Store the updated license map in the LICENSE_STORE
             LICENSE_STORE.with(|r| r.borrow_mut().insert(license_key.to_tuple(), license_map));
+            true
         } else {
             ic_cdk::println!("License already exists for user: {:?}", user_pid);
+            false
         }
     }
 
+    pub fn has_active_license(license_key: &LicenseKey, user_pid: Principal, now: u64) -> bool {
+        get_license(license_key.to_tuple()).is_some_and(|license_map| {
+            license_map
+                .0
+                .get(&user_pid)
+                .is_some_and(|license| license.is_active(now))
+        })
+    }
+
     pub fn remove_track_license(track_id: u64, user_pid: Principal) {
         let license_key = LicenseKey::new(None, Some(track_id));
 
@@ -1013,14 +1825,15 @@ pub mod payment {
     use std::time::Duration;
 
     use crate::{
-        pay::{token_balance, token_fee, token_transfer},
+        pay::{icrc2_transfer_from, token_balance, token_fee, token_recipient, token_transfer},
         utils::generate_order_subaccount,
     };
 
     use super::*;
     use crate::utils::check_page_size;
     use canistore_types::payment::{
-        PaymentInfo, PaymentStatus, QueryCommonReq, QueryOrder, QuerySort,
+        add_token_amount, PaymentInfo, PaymentStatus, QueryCommonReq, QueryOrder, QuerySort,
+        MAX_STALE_ORDERS_PER_SWEEP, PAYMENT_ORDER_TIMEOUT, STALE_ORDER_SWEEP_INTERVAL_SECS,
     };
     use ic_cdk::api::time;
     use icrc_ledger_types::icrc1::account::Account;
@@ -1035,7 +1848,7 @@ pub mod payment {
         })
     }
 
-    pub fn create_award_order(
+    pub fn create_payment_order(
         order_id: u64,
         payer: Principal,
         source: String,
@@ -1055,6 +1868,13 @@ pub mod payment {
             verified_time: None,
             shared_time: None,
             created_time: time(),
+            delivered_time: None,
+            refund_tx: None,
+            refunded_amount: None,
+            swept_time: None,
+            consolidated_time: None,
+            consolidation_tried_time: None,
+            revenue_shares: None,
         };
 
         PAYMENT_STORE.with(|store| {
@@ -1075,7 +1895,11 @@ pub mod payment {
         }
     }
 
-    pub fn limit_orders(caller: Principal, req: &QueryCommonReq) -> (usize, bool, Vec<QueryOrder>) {
+    // Lists the orders of one payer, or every order of the space when payer is None.
+    pub fn limit_orders(
+        payer: Option<Principal>,
+        req: &QueryCommonReq,
+    ) -> (usize, bool, Vec<QueryOrder>) {
         let mut data = Vec::new();
         let (page, size) = check_page_size(req.page, req.size);
         let start = (page - 1) * size;
@@ -1098,7 +1922,7 @@ pub mod payment {
         for (_idx, (_key, order_wrapper)) in orders.iter().enumerate() {
             let order = &order_wrapper.0;
 
-            if order.payer != caller {
+            if payer.is_some_and(|payer| order.payer != payer) {
                 continue;
             }
 
@@ -1118,7 +1942,12 @@ pub mod payment {
         (total, has_more, data)
     }
 
-    pub async fn confirm_payment_order(order_id: u64) -> Result<bool, String> {
+    // With an ICRC-2 payer account the order amount is pulled from its allowance,
+    // otherwise the deposit to the order subaccount is verified.
+    pub async fn confirm_payment_order(
+        order_id: u64,
+        checkout: Option<Account>,
+    ) -> Result<bool, String> {
         let order = get_payment_order(order_id);
 
         let mut check_order = match order {
@@ -1126,22 +1955,118 @@ pub mod payment {
             None => return Err(format!("Order with id {} not found", order_id)),
         };
 
+        // Only the payer can pay the order from its allowance
+        if checkout.is_some_and(|from| from.owner != check_order.payer) {
+            return Err("Caller is not the payer of the order".to_string());
+        }
+
         // Verify the payment order
-        let is_verified = verify_payment_order(&mut check_order).await;
+        let result = match checkout {
+            Some(from) if check_order.status == PaymentStatus::Unpaid => {
+                pull_payment_order(&mut check_order, from).await
+            }
+            _ => match verify_payment_order(&mut check_order).await {
+                true => Ok(()),
+                false => Err("Order verification failed".to_string()),
+            },
+        };
 
-        PAYMENT_STORE.with(|store| {
+        // Re-read the stored order after the await, only the call that moves it
+        // to Paid records the income.
+        let newly_paid = PAYMENT_STORE.with(|store| {
             let mut store = store.borrow_mut();
-            // store.remove(&order_id);
-            store.insert(order_id, PaymentOrderWrapper(check_order));
+            let stored = store.get(&order_id).map(|order| order.into_inner().status);
+            match stored {
+                Some(PaymentStatus::Paid) | Some(PaymentStatus::Refunded) => false,
+                _ => {
+                    // The split sheet at payment time applies, later edits do not change it
+                    if check_order.status == PaymentStatus::Paid {
+                        check_order.revenue_shares = payout::revenue_shares(&check_order);
+                    }
+                    store.insert(order_id, PaymentOrderWrapper(check_order.clone()));
+                    check_order.status == PaymentStatus::Paid
+                }
+            }
         });
 
-        if is_verified {
-            Ok(true)
-        } else {
-            Err("Order verification failed".to_string())
+        result?;
+        if newly_paid {
+            journal::record_order_paid(&check_order);
+            state::load();
+            state::with_mut(|space| {
+                add_token_amount(
+                    &mut space.token_income,
+                    &check_order.token,
+                    check_order.amount_paid,
+                )
+            });
+            state::save();
+
+            // Failed payouts are retried by the treasury sweep
+            payout::queue_payouts(&check_order);
+            payout::distribute(&check_order).await;
+        }
+        Ok(true)
+    }
+
+    // The order is stored as verifying before the ledger call, so it is never charged twice.
+    async fn pull_payment_order(order: &mut PaymentOrder, from: Account) -> Result<(), String> {
+        if order.created_time + PAYMENT_ORDER_TIMEOUT < time() {
+            order.status = PaymentStatus::TimedOut;
+            return Err("Order has timed out".to_string());
+        }
+
+        order.status = PaymentStatus::Verifying;
+        PAYMENT_STORE.with(|store| {
+            store
+                .borrow_mut()
+                .insert(order.id, PaymentOrderWrapper(order.clone()))
+        });
+
+        let subaccount: Option<[u8; 32]> = generate_order_subaccount(order.payer, order.id)
+            .try_into()
+            .ok();
+        let to = Account {
+            owner: ic_cdk::id(),
+            subaccount,
+        };
+
+        match icrc2_transfer_from(&order.token, from, to, order.amount).await {
+            Ok(_) => {
+                order.amount_paid = order.amount;
+                order.status = PaymentStatus::Paid;
+                order.verified_time = Some(time());
+                Ok(())
+            }
+            Err(err) => {
+                order.status = PaymentStatus::Unpaid;
+                Err(err)
+            }
         }
     }
 
+    // Marks a paid order as delivered, so that its goods are granted only once.
+    pub fn deliver_payment_order(order_id: u64) -> Result<PaymentOrder, String> {
+        PAYMENT_STORE.with(|store| {
+            let mut store = store.borrow_mut();
+            let mut order = match store.get(&order_id) {
+                Some(order) => order.into_inner(),
+                None => return Err(format!("Order with id {} not found", order_id)),
+            };
+
+            if order.status != PaymentStatus::Paid {
+                return Err("Order is not paid".to_string());
+            }
+            if order.delivered_time.is_some() {
+                return Err("Order has already been delivered".to_string());
+            }
+
+            order.delivered_time = Some(time());
+            store.insert(order_id, PaymentOrderWrapper(order.clone()));
+            Ok(order)
+        })
+    }
+
     pub async fn refund_payment_order(
         order_id: u64,
         caller: Principal,
@@ -1175,6 +2100,107 @@ pub mod payment {
         }
     }
 
+    // Times out unsettled orders and refunds deposits that arrived too late. Orders checked
+    // least recently go first, so a backlog of stale orders is worked through over several runs.
+    pub async fn sweep_stale_orders(now: u64) -> usize {
+        let mut orders: Vec<PaymentOrder> = PAYMENT_STORE.with(|store| {
+            store
+                .borrow()
+                .iter()
+                .map(|(_, order)| order.into_inner())
+                .filter(|order| order.needs_sweep(now))
+                .collect()
+        });
+        orders.sort_by_key(|order| order.swept_time.unwrap_or(0));
+        orders.truncate(MAX_STALE_ORDERS_PER_SWEEP);
+
+        let mut refunded = 0;
+        for mut order in orders {
+            if refund_stale_order(&mut order, now).await {
+                refunded += 1;
+            }
+
+            PAYMENT_STORE.with(|store| {
+                let mut store = store.borrow_mut();
+                // Keep orders that were settled while the ledger was being called
+                if let Some(current) = store.get(&order.id) {
+                    if current.0.status == PaymentStatus::Paid
+                        || current.0.status == PaymentStatus::Refunded
+                    {
+                        return;
+                    }
+                }
+                store.insert(order.id, PaymentOrderWrapper(order));
+            });
+        }
+        refunded
+    }
+
+    // Settled orders are skipped, they may have been confirmed since the sweep listed them
+    fn is_stale_order(order_id: u64, now: u64) -> bool {
+        match get_payment_order(order_id) {
+            Some(order) => {
+                order.status != PaymentStatus::Paid
+                    && order.status != PaymentStatus::Refunded
+                    && order.created_time + PAYMENT_ORDER_TIMEOUT < now
+            }
+            None => false,
+        }
+    }
+
+    async fn refund_stale_order(order: &mut PaymentOrder, now: u64) -> bool {
+        order.swept_time = Some(now);
+        if !is_stale_order(order.id, now) {
+            return false;
+        }
+        order.status = PaymentStatus::TimedOut;
+
+        let subaccount: Option<[u8; 32]> = generate_order_subaccount(order.payer, order.id)
+            .try_into()
+            .ok();
+        let order_account = Account {
+            owner: ic_cdk::id(),
+            subaccount,
+        };
+        let balance = token_balance(&order.token, order_account).await;
+        let fee = token_fee(&order.token);
+        if balance <= fee || !is_stale_order(order.id, now) {
+            return false;
+        }
+
+        let refund_to = token_recipient(&order.token, order.payer);
+        match token_transfer(&order.token, subaccount, refund_to, balance - fee).await {
+            Ok(height) => {
+                order.amount_paid = balance;
+                order.refunded_amount = Some(balance - fee);
+                order.status = PaymentStatus::Refunded;
+                order.refund_tx = Some(height);
+                order.verified_time = Some(time());
+                journal::record_refund(order, balance, height);
+                true
+            }
+            Err(err) => {
+                ic_cdk::print(format!("Failed to refund order {}: {}", order.id, err));
+                false
+            }
+        }
+    }
+
+    pub fn set_stale_order_sweep_timer() {
+        let timer_id = ic_cdk_timers::set_timer_interval(
+            Duration::from_secs(STALE_ORDER_SWEEP_INTERVAL_SECS),
+            || {
+                ic_cdk::spawn(async {
+                    let count = sweep_stale_orders(time()).await;
+                    if count > 0 {
+                        ic_cdk::print(format!("Refunded {} stale orders", count));
+                    }
+                })
+            },
+        );
+        TIMER_IDS.with(|timer_ids| timer_ids.borrow_mut().push(timer_id));
+    }
+
     async fn verify_payment_order(order: &mut PaymentOrder) -> bool {
         // If order is already paid, return true
         if order.status == PaymentStatus::Paid {
@@ -1189,13 +2215,19 @@ pub mod payment {
         // Update the status to verifying
         order.status = PaymentStatus::Verifying;
 
-        // Set timeout for 15 minutes (converted to nanoseconds)
-        let fifteen_minutes = Duration::from_secs(15 * 60).as_nanos() as u64;
-        if order.created_time + fifteen_minutes < time() {
+        // Orders must be paid within PAYMENT_ORDER_TIMEOUT
+        if order.created_time + PAYMENT_ORDER_TIMEOUT < time() {
             order.status = PaymentStatus::TimedOut;
             return false;
         }
 
+        // Store the claim before the ledger call, so concurrent confirms do not verify it twice.
+        PAYMENT_STORE.with(|store| {
+            store
+                .borrow_mut()
+                .insert(order.id, PaymentOrderWrapper(order.clone()))
+        });
+
         // Check the account balance based on the generated subaccount
         let subaccount: Option<[u8; 32]> = generate_order_subaccount(order.payer, order.id)
             .try_into()
@@ -1238,7 +2270,7 @@ pub mod payment {
             .ok();
 
         let payer_account = Account {
-            owner: order.payer,
+            owner: ic_cdk::id(),
             subaccount,
         };
 
@@ -1254,9 +2286,11 @@ pub mod payment {
 
         // Attempt to transfer the refund
         match token_transfer(&order.token, subaccount, refund_to, amount_to_refund).await {
-            Ok(_) => {
+            Ok(height) => {
                 // Update order status to refunded
                 order.status = PaymentStatus::Refunded;
+                order.refund_tx = Some(height);
+                journal::record_refund(order, balance, height);
                 order.verified_time = Some(time());
                 true
             }
@@ -1265,41 +2299,953 @@ pub mod payment {
     }
 }
 
-pub mod share {
-    use canistore_types::space::SharedTrackListResp;
-
+pub mod payout {
     use super::*;
+    use crate::{
+        pay::{token_fee, token_recipient, token_transfer},
+        utils::{check_page_size, generate_order_subaccount},
+    };
+    use canistore_types::payment::{
+        JournalAccount, JournalKind, PayoutStatus, QueryCommonReq, QuerySort, RevenueShare,
+        REVENUE_SHARE_TOTAL_RATIO,
+    };
+    use ic_cdk::api::time;
 
-    pub fn total_shares() -> u64 {
-        SHARE_STORE.with(|r| r.borrow().len())
+    // License orders use the split sheet of the track, falling back to its album,
+    // album license orders use the split sheet of the album.
+    // Award orders use the split sheet of the awarded album.
+    pub fn revenue_shares(order: &PaymentOrder) -> Option<Vec<RevenueShare>> {
+        let shares = match &order.payment_type {
+            PaymentType::LicensePrice(price) if price.album_id.is_some() => {
+                album::get_album(price.album_id?)?
+                    .into_inner()
+                    .revenue_shares
+            }
+            PaymentType::LicensePrice(price) => {
+                let track = track::get_track(price.track_id)?.into_inner();
+                track.revenue_shares.or_else(|| {
+                    track
+                        .album_id
+                        .and_then(album::get_album)
+                        .and_then(|album| album.into_inner().revenue_shares)
+                })
+            }
+            PaymentType::Award(price) => {
+                let album_id = price.aid.parse::<u64>().ok()?;
+                album::get_album(album_id)?.into_inner().revenue_shares
+            }
+            _ => None,
+        };
+        shares.filter(|shares| !shares.is_empty())
     }
 
-    pub fn total_shares_album() -> u64 {
-        let track_ids: Vec<u64> = SHARE_STORE.with(|r| {
-            let store = r.borrow();
-            store.iter().map(|(track_id, _)| track_id).collect()
-        });
-
-        let tracks = track::get_tracks_by_ids(track_ids);
-
-        // Collect unique album IDs
-        let unique_album_ids: std::collections::HashSet<u64> = tracks
-            .into_iter()
-            .filter_map(|track| track.album_id)
-            .collect();
+    pub fn get_order_payouts(order_id: u64) -> Vec<PayoutRecord> {
+        PAYOUT_STORE.with(|r| {
+            r.borrow()
+                .range((order_id, 0)..=(order_id, u32::MAX))
+                .map(|(_, wrapper)| wrapper.into_inner())
+                .collect()
+        })
+    }
 
-        unique_album_ids.len() as u64
+    fn save_payout(index: u32, payout: PayoutRecord) {
+        PAYOUT_STORE.with(|r| {
+            r.borrow_mut()
+                .insert((payout.order_id, index), PayoutRecordWrapper(payout))
+        });
     }
 
-    pub fn get_share(track_id: u64) -> Option<SharedTrackWrapper> {
-        SHARE_STORE.with(|r| r.borrow().get(&track_id))
+    // Splits the paid amount of the order between the recipients of the split sheet captured
+    // at payment. Payouts are recorded as queued, only once per order.
+    pub fn queue_payouts(order: &PaymentOrder) {
+        let shares = match &order.revenue_shares {
+            Some(shares) if get_order_payouts(order.id).is_empty() => shares,
+            _ => return,
+        };
+        let now = time();
+        let fee = token_fee(&order.token);
+
+        for (index, share) in shares.iter().enumerate() {
+            let gross = (order.amount_paid as u128 * share.ratio as u128
+                / REVENUE_SHARE_TOTAL_RATIO as u128) as u64;
+            let (amount, status) = if gross > fee {
+                (gross - fee, PayoutStatus::Queued)
+            } else {
+                (0, PayoutStatus::Skipped)
+            };
+            save_payout(
+                index as u32,
+                PayoutRecord {
+                    order_id: order.id,
+                    recipient: share.recipient,
+                    ratio: share.ratio,
+                    token: order.token.clone(),
+                    amount,
+                    status,
+                    block_height: None,
+                    error: None,
+                    created: now,
+                    updated: now,
+                },
+            );
+        }
     }
 
-    // pub fn get_share_list() -> Vec<SharedTrack> {
-    //     SHARE_STORE.with(|r| {
-    //         let store = r.borrow();
-    //         store
-    //             .iter()
+    // Transfers the queued and failed payouts of the order.
+    pub async fn distribute(order: &PaymentOrder) {
+        // Claim the payouts before awaiting, so concurrent calls skip them.
+        let mut claimed: Vec<(u32, PayoutRecord)> = Vec::new();
+        PAYOUT_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let unpaid: Vec<((u64, u32), PayoutRecord)> = store
+                .range((order.id, 0)..=(order.id, u32::MAX))
+                .map(|(key, wrapper)| (key, wrapper.into_inner()))
+                .filter(|(_, payout)| payout.is_unpaid())
+                .collect();
+            for (key, mut payout) in unpaid {
+                payout.status = PayoutStatus::Pending;
+                store.insert(key, PayoutRecordWrapper(payout.clone()));
+                claimed.push((key.1, payout));
+            }
+        });
+
+        let subaccount: Option<[u8; 32]> = generate_order_subaccount(order.payer, order.id)
+            .try_into()
+            .ok();
+        for (index, mut payout) in claimed {
+            let to = token_recipient(&payout.token, payout.recipient);
+            match token_transfer(&payout.token, subaccount, to, payout.amount).await {
+                Ok(height) => {
+                    payout.status = PayoutStatus::Paid;
+                    payout.block_height = Some(height);
+                    payout.error = None;
+                    journal::add_transfer(
+                        JournalKind::Payout,
+                        Some(order.id),
+                        &payout.token,
+                        payout.amount,
+                        JournalAccount::Payouts,
+                        JournalAccount::OrderEscrow,
+                        Some(height),
+                    );
+                }
+                Err(err) => {
+                    payout.status = PayoutStatus::Failed;
+                    payout.error = Some(err);
+                }
+            }
+            payout.updated = time();
+            save_payout(index, payout);
+        }
+    }
+
+    pub fn limit_payouts(
+        recipient: Principal,
+        req: &QueryCommonReq,
+    ) -> (usize, bool, Vec<PayoutRecord>) {
+        let (page, size) = check_page_size(req.page, req.size);
+        let start = (page - 1) * size;
+
+        let mut payouts: Vec<PayoutRecord> = PAYOUT_STORE.with(|r| {
+            r.borrow()
+                .iter()
+                .map(|(_, wrapper)| wrapper.into_inner())
+                .filter(|payout| payout.recipient == recipient)
+                .collect()
+        });
+        if let QuerySort::TimeDesc = req.sort {
+            payouts.reverse();
+        }
+
+        let total = payouts.len();
+        let data = payouts.into_iter().skip(start).take(size).collect();
+        (total, total > start + size, data)
+    }
+}
+
+pub mod treasury {
+    use super::*;
+    use crate::{
+        pay::{account_transfer, token_balance, token_fee},
+        utils::generate_order_subaccount,
+    };
+    use canistore_types::payment::{
+        JournalAccount, JournalKind, PaymentStatus, PayoutStatus, TreasuryBalance,
+        MAX_ORDERS_PER_CONSOLIDATION, TREASURY_SUBACCOUNT, TREASURY_SWEEP_INTERVAL_SECS,
+    };
+    use ic_cdk::api::time;
+    use icrc_ledger_types::icrc1::account::Account;
+
+    // Earnings are consolidated into a dedicated subaccount of the space canister
+    pub fn treasury_account() -> Account {
+        Account {
+            owner: ic_cdk::id(),
+            subaccount: Some(TREASURY_SUBACCOUNT),
+        }
+    }
+
+    // Least recently tried first, so orders with stuck payouts do not block the others.
+    fn unconsolidated_orders() -> Vec<PaymentOrder> {
+        let mut orders: Vec<PaymentOrder> = PAYMENT_STORE.with(|r| {
+            r.borrow()
+                .iter()
+                .map(|(_, wrapper)| wrapper.into_inner())
+                .filter(|order| {
+                    order.status == PaymentStatus::Paid && order.consolidated_time.is_none()
+                })
+                .collect()
+        });
+        orders.sort_by_key(|order| order.consolidation_tried_time.unwrap_or(0));
+        orders
+    }
+
+    fn mark_tried(order_id: u64, now: u64) {
+        PAYMENT_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            if let Some(mut order) = store.get(&order_id).map(|order| order.into_inner()) {
+                order.consolidation_tried_time = Some(now);
+                store.insert(order_id, PaymentOrderWrapper(order));
+            }
+        });
+    }
+
+    // Marks the order as consolidated before any transfer, so concurrent sweeps skip it.
+    fn claim_order(order_id: u64, claimed_time: Option<u64>) -> Option<PaymentOrder> {
+        PAYMENT_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let mut order = store.get(&order_id)?.into_inner();
+            if claimed_time.is_some() && order.consolidated_time.is_some() {
+                return None;
+            }
+            order.consolidated_time = claimed_time;
+            store.insert(order_id, PaymentOrderWrapper(order.clone()));
+            Some(order)
+        })
+    }
+
+    // Orders with revenue splits are only swept once every payout is settled,
+    // queued and failed payouts are retried first.
+    async fn settle_payouts(order: &PaymentOrder) -> bool {
+        payout::queue_payouts(order);
+        if payout::get_order_payouts(order.id)
+            .iter()
+            .any(|payout| payout.is_unpaid())
+        {
+            payout::distribute(order).await;
+        }
+        payout::get_order_payouts(order.id).iter().all(|payout| {
+            payout.status == PayoutStatus::Paid || payout.status == PayoutStatus::Skipped
+        })
+    }
+
+    // Moves what is left in the subaccounts of paid orders to the treasury account.
+    pub async fn consolidate_paid_orders(now: u64) -> usize {
+        let mut orders = unconsolidated_orders();
+        orders.truncate(MAX_ORDERS_PER_CONSOLIDATION);
+
+        let mut consolidated = 0;
+        for order in orders {
+            mark_tried(order.id, now);
+            if !settle_payouts(&order).await {
+                continue;
+            }
+            let order = match claim_order(order.id, Some(now)) {
+                Some(order) => order,
+                None => continue,
+            };
+
+            let subaccount: Option<[u8; 32]> = generate_order_subaccount(order.payer, order.id)
+                .try_into()
+                .ok();
+            let order_account = Account {
+                owner: ic_cdk::id(),
+                subaccount,
+            };
+            let balance = token_balance(&order.token, order_account).await;
+            let fee = token_fee(&order.token);
+            if balance <= fee {
+                consolidated += 1;
+                continue;
+            }
+
+            match account_transfer(&order.token, subaccount, treasury_account(), balance - fee)
+                .await
+            {
+                Ok(height) => {
+                    journal::add_transfer(
+                        JournalKind::Consolidation,
+                        Some(order.id),
+                        &order.token,
+                        balance - fee,
+                        JournalAccount::Treasury,
+                        JournalAccount::OrderEscrow,
+                        Some(height),
+                    );
+                    consolidated += 1;
+                }
+                Err(err) => {
+                    ic_cdk::print(format!("Failed to consolidate order {}: {}", order.id, err));
+                    claim_order(order.id, None);
+                }
+            }
+        }
+        consolidated
+    }
+
+    pub fn set_treasury_sweep_timer() {
+        let timer_id = ic_cdk_timers::set_timer_interval(
+            Duration::from_secs(TREASURY_SWEEP_INTERVAL_SECS),
+            || {
+                ic_cdk::spawn(async {
+                    let count = consolidate_paid_orders(time()).await;
+                    if count > 0 {
+                        ic_cdk::print(format!("Consolidated {} paid orders", count));
+                    }
+                })
+            },
+        );
+        TIMER_IDS.with(|timer_ids| timer_ids.borrow_mut().push(timer_id));
+    }
+
+    // What is left in order subaccounts after payouts and fees is the escrow balance of the journal.
+    pub async fn balances() -> Vec<TreasuryBalance> {
+        let mut balances = Vec::new();
+        for token in state::accepted_tokens() {
+            let balance = token_balance(&token, treasury_account()).await;
+            let unconsolidated = journal::balances(Some(&token), 0, 0)
+                .into_iter()
+                .find(|balance| balance.account == JournalAccount::OrderEscrow)
+                .map_or(0, |balance| balance.debit.saturating_sub(balance.credit));
+            balances.push(TreasuryBalance {
+                token,
+                balance,
+                unconsolidated,
+            });
+        }
+        balances
+    }
+}
+
+pub mod journal {
+    use super::*;
+    use crate::{
+        pay::token_fee,
+        utils::{check_page_size, format_datetime},
+    };
+    use canistore_types::payment::{
+        JournalAccount, JournalBalance, JournalKind, QueryJournalReq, QuerySort,
+    };
+    use ic_cdk::api::time;
+
+    pub fn add_entry(
+        kind: JournalKind,
+        order_id: Option<u64>,
+        token: &str,
+        amount: u64,
+        debit: JournalAccount,
+        credit: JournalAccount,
+        block_height: Option<u64>,
+    ) {
+        if amount == 0 {
+            return;
+        }
+        JOURNAL_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let id = store.last_key_value().map_or(1, |(id, _)| id + 1);
+            store.insert(
+                id,
+                JournalEntryWrapper(JournalEntry {
+                    id,
+                    kind,
+                    order_id,
+                    token: token.to_string(),
+                    amount,
+                    debit,
+                    credit,
+                    block_height,
+                    created: time(),
+                }),
+            );
+        });
+    }
+
+    // A ledger transfer moves the amount and burns the fee, both from the credited account
+    pub fn add_transfer(
+        kind: JournalKind,
+        order_id: Option<u64>,
+        token: &str,
+        amount: u64,
+        debit: JournalAccount,
+        credit: JournalAccount,
+        block_height: Option<u64>,
+    ) {
+        add_entry(kind, order_id, token, amount, debit, credit, block_height);
+        add_entry(
+            JournalKind::Fee,
+            order_id,
+            token,
+            token_fee(token),
+            JournalAccount::LedgerFees,
+            credit,
+            block_height,
+        );
+    }
+
+    // The paid amount is revenue held in the order subaccount until it is paid out or consolidated
+    pub fn record_order_paid(order: &PaymentOrder) {
+        add_entry(
+            JournalKind::OrderPaid,
+            Some(order.id),
+            &order.token,
+            order.amount_paid,
+            JournalAccount::OrderEscrow,
+            JournalAccount::Revenue,
+            None,
+        );
+    }
+
+    // A deposit on an unpaid order is owed back to the payer, who also bears the transfer fee
+    pub fn record_refund(order: &PaymentOrder, deposit: u64, block_height: u64) {
+        let token = order.token.as_str();
+        let fee = token_fee(token);
+        add_entry(
+            JournalKind::Refund,
+            Some(order.id),
+            token,
+            deposit,
+            JournalAccount::OrderEscrow,
+            JournalAccount::Refunds,
+            None,
+        );
+        add_entry(
+            JournalKind::Refund,
+            Some(order.id),
+            token,
+            deposit.saturating_sub(fee),
+            JournalAccount::Refunds,
+            JournalAccount::OrderEscrow,
+            Some(block_height),
+        );
+        add_entry(
+            JournalKind::Fee,
+            Some(order.id),
+            token,
+            fee,
+            JournalAccount::LedgerFees,
+            JournalAccount::OrderEscrow,
+            Some(block_height),
+        );
+    }
+
+    fn in_period(entry: &JournalEntry, token: Option<&str>, start: u64, end: u64) -> bool {
+        token.map_or(true, |token| entry.token == token)
+            && entry.created >= start
+            && (end == 0 || entry.created < end)
+    }
+
+    fn entries(token: Option<&str>, start: u64, end: u64) -> Vec<JournalEntry> {
+        JOURNAL_STORE.with(|r| {
+            r.borrow()
+                .iter()
+                .map(|(_, wrapper)| wrapper.into_inner())
+                .filter(|entry| in_period(entry, token, start, end))
+                .collect()
+        })
+    }
+
+    pub fn limit_entries(req: &QueryJournalReq) -> (usize, bool, Vec<JournalEntry>) {
+        let (page, size) = check_page_size(req.page as usize, req.size as usize);
+        let start = (page - 1) * size;
+
+        let mut entries = entries(req.token.as_deref(), req.start, req.end);
+        if let QuerySort::TimeDesc = req.sort {
+            entries.reverse();
+        }
+
+        let total = entries.len();
+        let data = entries.into_iter().skip(start).take(size).collect();
+        (total, total > start + size, data)
+    }
+
+    pub fn balances(token: Option<&str>, start: u64, end: u64) -> Vec<JournalBalance> {
+        let mut totals: BTreeMap<(String, JournalAccount), (u64, u64)> = BTreeMap::new();
+        for entry in entries(token, start, end) {
+            totals
+                .entry((entry.token.clone(), entry.debit))
+                .or_default()
+                .0 += entry.amount;
+            totals.entry((entry.token, entry.credit)).or_default().1 += entry.amount;
+        }
+
+        totals
+            .into_iter()
+            .map(|((token, account), (debit, credit))| JournalBalance {
+                token,
+                account,
+                debit,
+                credit,
+            })
+            .collect()
+    }
+
+    pub fn statement_csv(start: u64, end: u64) -> String {
+        let mut csv = String::from(JournalEntry::CSV_HEADER);
+        for entry in entries(None, start, end) {
+            csv.push('\n');
+            csv.push_str(&entry.to_csv_row(&format_datetime(entry.created)));
+        }
+        csv.push('\n');
+        csv
+    }
+}
+
+pub mod release {
+    use super::*;
+    use crate::canister_service::CanisterService;
+    use canistore_types::{constant::CanisterType, platform::TrackInfo, space::AlbumStatus};
+    use ic_cdk::api::time;
+
+    // Only albums scheduled through schedule_album_release are published, release_at alone
+    // is album metadata
+    fn is_scheduled(album: &Album) -> bool {
+        album.status == AlbumStatus::Draft
+            && album.release_at.is_some()
+            && album.release_status.is_some()
+    }
+
+    fn scheduled_albums() -> Vec<Album> {
+        ALBUM_STORE.with(|r| {
+            r.borrow()
+                .iter()
+                .map(|(_, wrapper)| wrapper.into_inner())
+                .filter(is_scheduled)
+                .collect()
+        })
+    }
+
+    pub fn next_release_at() -> Option<u64> {
+        scheduled_albums()
+            .iter()
+            .filter_map(|album| album.release_at)
+            .min()
+    }
+
+    // Flips the due albums to their release status, tracks of public albums become public.
+    pub fn publish_due_albums(now: u64) -> Vec<Album> {
+        let mut due: Vec<Album> = scheduled_albums()
+            .into_iter()
+            .filter(|album| {
+                album
+                    .release_at
+                    .map_or(false, |release_at| release_at <= now)
+            })
+            .collect();
+
+        for album in due.iter_mut() {
+            album.status = album.release_status.take().unwrap_or(AlbumStatus::Public);
+            album.updated = now;
+            ALBUM_STORE.with(|r| r.borrow_mut().insert(album.id, AlbumWrapper(album.clone())));
+
+            if album.status != AlbumStatus::Public {
+                continue;
+            }
+            for track in track::get_tracks_by_ids(album.track_ids.clone()) {
+                if track.public_at == 0 {
+                    let _ = track::set_track_public_status(track.id, now);
+                }
+            }
+        }
+        due
+    }
+
+    async fn share_album_tracks(album: &Album, channel_id: u64) -> Result<(), String> {
+        let tracks: Vec<Track> = track::get_tracks_by_ids(album.track_ids.clone())
+            .into_iter()
+            .filter(|track| share::get_share(track.id).is_none())
+            .collect();
+        if tracks.is_empty() {
+            return Ok(());
+        }
+
+        let track_ids: Vec<u64> = tracks.iter().map(|track| track.id).collect();
+        let track_infos: Vec<TrackInfo> = tracks
+            .iter()
+            .enumerate()
+            .map(|(i, track)| {
+                TrackInfo::new(album, track, ic_cdk::id(), i as u64, None, None, None)
+            })
+            .collect();
+
+        let env = state::get_env();
+        let platform_service = CanisterService::init(&env, &CanisterType::Platform)?;
+        match platform_service
+            .batch_add_tracks_to_channel(channel_id, track_infos)
+            .await
+        {
+            Ok((Ok(()),)) => share::batch_create_share(track_ids, channel_id, time()),
+            Ok((Err(msg),)) => Err(msg),
+            Err((code, msg)) => Err(format!("{}: {}", code as u8, msg)),
+        }
+    }
+
+    // Failed messages are kept locally and retried by the message timer.
+    async fn send_release_messages(album: &Album) {
+        for track in track::get_tracks_by_ids(album.track_ids.clone()) {
+            state::load();
+            let message_id = state::with_mut(|space| {
+                let message_id = space.next_message_id;
+                space.next_message_id += 1;
+                message_id
+            });
+            state::save();
+
+            let (msg_share_track, msg_resource) =
+                track.to_msg(album.producer, album.cover_image.clone());
+            let _ = message::send_message(
+                MessageType::Create,
+                "MsgShareTrack",
+                message_id,
+                msg_share_track,
+                msg_resource,
+            )
+            .await;
+        }
+    }
+
+    // Shares the tracks of released public albums to their platform channel and announces them
+    // to the indexer when search indexing is enabled, subscription tracks stay gated.
+    pub async fn announce_releases(albums: Vec<Album>) {
+        let enable_search_index = state::with(|space| space.enable_search_index);
+        for album in albums {
+            if album.status != AlbumStatus::Public {
+                continue;
+            }
+            if let Some(channel_id) = album.release_channel_id {
+                if let Err(err) = share_album_tracks(&album, channel_id).await {
+                    ic_cdk::print(format!("Failed to share album {}: {}", album.id, err));
+                }
+            }
+            if enable_search_index {
+                send_release_messages(&album).await;
+            }
+        }
+    }
+
+    // Keeps a single timer armed for the earliest scheduled release. Timers do not
+    // survive upgrades, so this also runs in post_upgrade.
+    pub fn schedule_next_release() {
+        if let Some(timer_id) = RELEASE_TIMER.with(|timer| timer.borrow_mut().take()) {
+            ic_cdk_timers::clear_timer(timer_id);
+        }
+        let release_at = match next_release_at() {
+            Some(release_at) => release_at,
+            None => return,
+        };
+
+        let delay = Duration::from_nanos(release_at.saturating_sub(time()));
+        let timer_id = ic_cdk_timers::set_timer(delay, || {
+            RELEASE_TIMER.with(|timer| timer.borrow_mut().take());
+            let albums = publish_due_albums(time());
+            schedule_next_release();
+            if !albums.is_empty() {
+                ic_cdk::print(format!("Released {} albums", albums.len()));
+                ic_cdk::spawn(announce_releases(albums));
+            }
+        });
+        RELEASE_TIMER.with(|timer| *timer.borrow_mut() = Some(timer_id));
+    }
+}
+
+pub mod follower {
+    use super::*;
+    use crate::utils::check_page_size;
+    use canistore_types::payment::{QueryCommonReq, QuerySort};
+
+    pub fn total() -> u64 {
+        FOLLOWER_STORE.with(|r| r.borrow().len())
+    }
+
+    // Returns true if the follower list changed.
+    pub fn set_follower(pid: Principal, follow: bool) -> bool {
+        let seq = FOLLOWER_STORE.with(|r| r.borrow().get(&pid));
+        match (follow, seq) {
+            (true, None) => {
+                let seq = FOLLOWER_SEQ_STORE.with(|r| {
+                    let mut store = r.borrow_mut();
+                    let seq = store.last_key_value().map_or(1, |(seq, _)| seq + 1);
+                    store.insert(seq, pid);
+                    seq
+                });
+                FOLLOWER_STORE.with(|r| r.borrow_mut().insert(pid, seq));
+                true
+            }
+            (false, Some(seq)) => {
+                FOLLOWER_SEQ_STORE.with(|r| r.borrow_mut().remove(&seq));
+                FOLLOWER_STORE.with(|r| r.borrow_mut().remove(&pid));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Returns the users that were following.
+    pub fn remove_followers(pids: &[Principal]) -> Vec<Principal> {
+        pids.iter()
+            .filter(|pid| set_follower(**pid, false))
+            .cloned()
+            .collect()
+    }
+
+    // Only one follow change per user waits for the user canister at a time.
+    pub fn try_lock(pid: Principal) -> bool {
+        FOLLOW_LOCKS.with(|r| r.borrow_mut().insert(pid))
+    }
+
+    pub fn unlock(pid: &Principal) {
+        FOLLOW_LOCKS.with(|r| r.borrow_mut().remove(pid));
+    }
+
+    pub fn migrate_legacy_followers() {
+        let followers = state::take_legacy_followers();
+        if followers.is_empty() {
+            return;
+        }
+        for pid in followers {
+            set_follower(pid, true);
+        }
+        state::with_mut(|space| space.total_followers = total());
+        state::save();
+    }
+
+    pub fn limit_followers(req: &QueryCommonReq) -> (usize, bool, Vec<Principal>) {
+        let (page, size) = check_page_size(req.page, req.size);
+        let start = (page - 1) * size;
+
+        FOLLOWER_SEQ_STORE.with(|r| {
+            let store = r.borrow();
+            let total = store.len() as usize;
+            let data = match req.sort {
+                QuerySort::TimeAsc => store
+                    .iter()
+                    .skip(start)
+                    .take(size)
+                    .map(|(_, pid)| pid)
+                    .collect(),
+                QuerySort::TimeDesc => {
+                    let end = total.saturating_sub(start);
+                    let begin = end.saturating_sub(size);
+                    let mut data: Vec<Principal> = store
+                        .iter()
+                        .skip(begin)
+                        .take(end - begin)
+                        .map(|(_, pid)| pid)
+                        .collect();
+                    data.reverse();
+                    data
+                }
+            };
+            (total, total > start + size, data)
+        })
+    }
+}
+
+pub mod role_audit {
+    use super::*;
+    use crate::utils::check_page_size;
+    use canistore_types::payment::{QueryCommonReq, QuerySort};
+    use ic_cdk::api::time;
+
+    pub fn add_entry(operator: Principal, pid: Principal, roles: Vec<ManagerRole>) {
+        ROLE_AUDIT_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let id = store.last_key_value().map_or(1, |(id, _)| id + 1);
+            store.insert(
+                id,
+                RoleAuditEntryWrapper(RoleAuditEntry {
+                    id,
+                    operator,
+                    pid,
+                    roles,
+                    created: time(),
+                }),
+            );
+        });
+    }
+
+    // Records the migration of legacy managers, the operator is the canister itself.
+    pub fn migrate_legacy_managers() {
+        let migrated = state::migrate_manager_roles();
+        if migrated.is_empty() {
+            return;
+        }
+        state::save();
+        for pid in migrated {
+            add_entry(ic_cdk::id(), pid, ManagerRole::legacy());
+        }
+    }
+
+    pub fn limit_entries(req: &QueryCommonReq) -> (usize, bool, Vec<RoleAuditEntry>) {
+        let (page, size) = check_page_size(req.page, req.size);
+        let start = (page - 1) * size;
+
+        let mut entries: Vec<RoleAuditEntry> = ROLE_AUDIT_STORE.with(|r| {
+            r.borrow()
+                .iter()
+                .map(|(_, wrapper)| wrapper.into_inner())
+                .collect()
+        });
+        if let QuerySort::TimeDesc = req.sort {
+            entries.reverse();
+        }
+
+        let total = entries.len();
+        let data = entries.into_iter().skip(start).take(size).collect();
+        (total, total > start + size, data)
+    }
+}
+
+pub mod award {
+    use super::*;
+    use crate::utils::check_page_size;
+    use canistore_types::payment::{
+        add_token_amount, AwardSupporter, QueryAward, QueryAwardReq, QuerySort,
+    };
+
+    // Records a confirmed award and adds it to the totals of the awarded album.
+    pub fn add_award(award: AwardOrder) -> Result<AwardOrder, String> {
+        if AWARD_STORE.with(|r| r.borrow().contains_key(&award.id)) {
+            return Err(format!("Award with order ID {} already exists", award.id));
+        }
+
+        if let Ok(album_id) = award.album_id.parse::<u64>() {
+            ALBUM_STORE.with(|r| {
+                let mut store = r.borrow_mut();
+                if let Some(wrapper) = store.get(&album_id) {
+                    let mut album = wrapper.into_inner();
+                    album.total_awards = Some(album.total_awards.unwrap_or(0) + 1);
+                    let mut income = album.total_income.unwrap_or_default();
+                    add_token_amount(&mut income, &award.token, award.amount);
+                    album.total_income = Some(income);
+                    store.insert(album_id, AlbumWrapper(album));
+                }
+            });
+        }
+
+        AWARD_STORE.with(|r| {
+            r.borrow_mut()
+                .insert(award.id, AwardOrderWrapper(award.clone()))
+        });
+        Ok(award)
+    }
+
+    // Awards of albums the caller cannot see are left out.
+    fn awards_of(album_id: Option<&str>, visible: &dyn Fn(&str) -> bool) -> Vec<AwardOrder> {
+        AWARD_STORE.with(|r| {
+            r.borrow()
+                .iter()
+                .map(|(_, wrapper)| wrapper.into_inner())
+                .filter(|award| album_id.map_or(true, |id| award.album_id == id))
+                .filter(|award| visible(&award.album_id))
+                .collect()
+        })
+    }
+
+    pub fn limit_awards(
+        req: &QueryAwardReq,
+        visible: &dyn Fn(&str) -> bool,
+    ) -> (usize, bool, Vec<QueryAward>) {
+        let (page, size) = check_page_size(req.page as usize, req.size as usize);
+        let start = (page - 1) * size;
+
+        let album_id = (!req.album_id.is_empty()).then_some(req.album_id.as_str());
+        let mut awards = awards_of(album_id, visible);
+        if let QuerySort::TimeDesc = req.sort {
+            awards.reverse();
+        }
+
+        let total = awards.len();
+        let data = awards
+            .iter()
+            .skip(start)
+            .take(size)
+            .map(QueryAward::from)
+            .collect();
+        (total, total > start + size, data)
+    }
+
+    // Ranks contributors by the amount awarded in the given token.
+    pub fn top_supporters(
+        album_id: Option<&str>,
+        token: &str,
+        limit: usize,
+        visible: &dyn Fn(&str) -> bool,
+    ) -> Vec<AwardSupporter> {
+        let mut supporters: BTreeMap<Principal, AwardSupporter> = BTreeMap::new();
+        for award in awards_of(album_id, visible)
+            .into_iter()
+            .filter(|award| award.token == token)
+        {
+            let supporter = supporters
+                .entry(award.contributor)
+                .or_insert_with(|| AwardSupporter {
+                    contributor: award.contributor,
+                    token: award.token.clone(),
+                    amount: 0,
+                    count: 0,
+                    last_awarded: 0,
+                });
+            supporter.amount += award.amount;
+            supporter.count += 1;
+            supporter.last_awarded = supporter.last_awarded.max(award.created_time);
+        }
+
+        let mut supporters: Vec<AwardSupporter> = supporters.into_values().collect();
+        supporters.sort_by(|a, b| {
+            b.amount
+                .cmp(&a.amount)
+                .then(b.count.cmp(&a.count))
+                .then(a.last_awarded.cmp(&b.last_awarded))
+        });
+        supporters.truncate(limit);
+        supporters
+    }
+}
+
+pub mod share {
+    use canistore_types::space::SharedTrackListResp;
+
+    use super::*;
+
+    pub fn total_shares() -> u64 {
+        SHARE_STORE.with(|r| r.borrow().len())
+    }
+
+    pub fn total_shares_album() -> u64 {
+        let track_ids: Vec<u64> = SHARE_STORE.with(|r| {
+            let store = r.borrow();
+            store.iter().map(|(track_id, _)| track_id).collect()
+        });
+
+        let tracks = track::get_tracks_by_ids(track_ids);
+
+        // Collect unique album IDs
+        let unique_album_ids: std::collections::HashSet<u64> = tracks
+            .into_iter()
+            .filter_map(|track| track.album_id)
+            .collect();
+
+        unique_album_ids.len() as u64
+    }
+
+    pub fn get_share(track_id: u64) -> Option<SharedTrackWrapper> {
+        SHARE_STORE.with(|r| r.borrow().get(&track_id))
+    }
+
+    // pub fn get_share_list() -> Vec<SharedTrack> {
+    //     SHARE_STORE.with(|r| {
+    //         let store = r.borrow();
+    //         store
+    //             .iter()
     //             .map(|(_, wrapper)| wrapper.into_inner().clone())
     //             .collect()
     //     })
@@ -1413,6 +3359,258 @@ pub mod post {
     }
 }
 
+pub mod category {
+    use super::*;
+    use canistore_types::space::{CategoryArgs, QueryCategory, MAX_CATEGORIES};
+
+    pub fn exists(id: u32) -> bool {
+        SPACE.with(|r| r.borrow().categories.iter().any(|c| c.id == id))
+    }
+
+    // The parent must exist and must not be the category itself or one of its descendants.
+    fn check_parent(categories: &[Category], id: u32, parent: Option<u32>) -> Result<(), String> {
+        let mut current = parent;
+        while let Some(pid) = current {
+            if pid == id {
+                return Err(
+                    CustomError::new(ErrorCode::DataInvalid, Some("Category cycle")).to_string(),
+                );
+            }
+            current = match categories.iter().find(|c| c.id == pid) {
+                Some(category) => category.parent,
+                None => {
+                    return Err(
+                        CustomError::new(ErrorCode::NoDataFound, Some("Parent category"))
+                            .to_string(),
+                    )
+                }
+            };
+        }
+        Ok(())
+    }
+
+    pub fn create_category(args: CategoryArgs) -> Result<u32, String> {
+        args.validate()?;
+        SPACE.with(|r| {
+            let mut space = r.borrow_mut();
+            if space.categories.len() >= MAX_CATEGORIES {
+                return Err(
+                    CustomError::new(ErrorCode::MaximumRecords, Some("categories")).to_string(),
+                );
+            }
+
+            // Ids start at 1 so that 0 never refers to a category.
+            let id = space.next_category_id + 1;
+            check_parent(&space.categories, id, args.parent)?;
+            space.categories.push(Category {
+                id,
+                category_type: args.category_type,
+                desc: args.desc,
+                parent: args.parent,
+                name: args.name.trim().to_string(),
+            });
+            space.next_category_id = id;
+            Ok(id)
+        })
+    }
+
+    pub fn update_category(id: u32, args: CategoryArgs) -> Result<(), String> {
+        args.validate()?;
+        SPACE.with(|r| {
+            let mut space = r.borrow_mut();
+            check_parent(&space.categories, id, args.parent)?;
+            match space.categories.iter_mut().find(|c| c.id == id) {
+                Some(category) => {
+                    category.category_type = args.category_type;
+                    category.desc = args.desc;
+                    category.parent = args.parent;
+                    category.name = args.name.trim().to_string();
+                    Ok(())
+                }
+                None => Err(CustomError::new(ErrorCode::NoDataFound, Some("Category")).to_string()),
+            }
+        })
+    }
+
+    // Categories with children cannot be deleted; albums and tracks lose the category.
+    pub fn delete_category(id: u32) -> Result<(), String> {
+        SPACE.with(|r| {
+            let mut space = r.borrow_mut();
+            if space.categories.iter().any(|c| c.parent == Some(id)) {
+                return Err(CustomError::new(
+                    ErrorCode::OperationNotAllowed,
+                    Some("Category has children"),
+                )
+                .to_string());
+            }
+            let len = space.categories.len();
+            space.categories.retain(|c| c.id != id);
+            if space.categories.len() == len {
+                return Err(CustomError::new(ErrorCode::NoDataFound, Some("Category")).to_string());
+            }
+            Ok(())
+        })?;
+
+        ALBUM_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let albums: Vec<(u64, Album)> = store
+                .iter()
+                .filter(|(_, wrapper)| wrapper.0.category_id == Some(id))
+                .map(|(key, wrapper)| (key, wrapper.into_inner()))
+                .collect();
+            for (key, mut album) in albums {
+                album.category_id = None;
+                store.insert(key, AlbumWrapper(album));
+            }
+        });
+        TRACK_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            let tracks: Vec<(u64, Track)> = store
+                .iter()
+                .filter(|(_, wrapper)| wrapper.0.category_id == Some(id))
+                .map(|(key, wrapper)| (key, wrapper.into_inner()))
+                .collect();
+            for (key, mut track) in tracks {
+                track.category_id = None;
+                store.insert(key, TrackWrapper(track));
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn get_category_tree() -> Vec<QueryCategory> {
+        fn children(categories: &[Category], parent: Option<u32>) -> Vec<QueryCategory> {
+            categories
+                .iter()
+                .filter(|c| c.parent == parent)
+                .map(|c| QueryCategory {
+                    id: c.id,
+                    name: c.name.clone(),
+                    children: children(categories, Some(c.id)),
+                })
+                .collect()
+        }
+
+        SPACE.with(|r| children(&r.borrow().categories, None))
+    }
+}
+
+pub mod comment {
+    use super::*;
+    use crate::utils::check_page_size;
+    use canistore_types::space::{CommentStatus, QueryComment, QueryCommentReq, QuerySort};
+
+    pub fn get_comment(album_id: u64, id: u32) -> Option<CommentWrapper> {
+        COMMENT_STORE.with(|r| r.borrow().get(&(album_id, id)))
+    }
+
+    pub fn add_comment(album_id: u64, comment: Comment) {
+        COMMENT_STORE.with(|r| {
+            r.borrow_mut()
+                .insert((album_id, comment.id), CommentWrapper(comment))
+        });
+    }
+
+    pub fn update_comment<R>(
+        album_id: u64,
+        id: u32,
+        f: impl FnOnce(&mut Comment) -> Result<R, String>,
+    ) -> Result<R, String> {
+        COMMENT_STORE.with(|r| {
+            let mut store = r.borrow_mut();
+            match store.get(&(album_id, id)) {
+                Some(wrapper) => {
+                    let mut comment = wrapper.into_inner();
+                    let res = f(&mut comment)?;
+                    store.insert((album_id, id), CommentWrapper(comment));
+                    Ok(res)
+                }
+                None => Err(CustomError::new(ErrorCode::NoDataFound, Some("Comment")).to_string()),
+            }
+        })
+    }
+
+    // Toggles the like of the caller and returns the new likes count.
+    pub fn toggle_like(album_id: u64, id: u32, caller: Principal) -> Result<u32, String> {
+        update_comment(album_id, id, |comment| {
+            if comment.status != CommentStatus::Visible {
+                return Err(CustomError::new(ErrorCode::NoDataFound, Some("Comment")).to_string());
+            }
+            let key = ((album_id, id), caller);
+            COMMENT_LIKE_STORE.with(|r| {
+                let mut store = r.borrow_mut();
+                if store.remove(&key).is_some() {
+                    comment.likes = comment.likes.saturating_sub(1);
+                } else {
+                    store.insert(key, ());
+                    comment.likes += 1;
+                }
+            });
+            Ok(comment.likes)
+        })
+    }
+
+    // Invisible comments are only listed for managers and their own commenter.
+    pub fn limit_comments(
+        album_id: u64,
+        req: &QueryCommentReq,
+        viewer: Principal,
+        is_manager: bool,
+    ) -> (usize, bool, Vec<QueryComment>) {
+        let (page, size) = check_page_size(req.page as usize, req.size as usize);
+        let start = (page - 1) * size;
+        let visible = |comment: &Comment| {
+            comment.status == CommentStatus::Visible || is_manager || comment.commenter_id == viewer
+        };
+
+        let mut comments: Vec<Comment> = Vec::new();
+        let mut latest_replies: BTreeMap<u32, Comment> = BTreeMap::new();
+        COMMENT_STORE.with(|r| {
+            for (_, wrapper) in r.borrow().range((album_id, 0)..=(album_id, u32::MAX)) {
+                let comment = wrapper.into_inner();
+                if !visible(&comment) {
+                    continue;
+                }
+                if comment.parent_id == req.parent_id
+                    && req
+                        .commenter_id
+                        .map_or(true, |pid| pid == comment.commenter_id)
+                {
+                    comments.push(comment.clone());
+                }
+                // comment ids are increasing, so the last reply seen is the latest one
+                if let Some(parent_id) = comment.parent_id {
+                    latest_replies.insert(parent_id, comment);
+                }
+            }
+        });
+
+        match req.sort {
+            QuerySort::TimeAsc => {}
+            QuerySort::TimeDesc => {
+                comments.reverse();
+            }
+        }
+
+        let total = comments.len();
+        let has_more = total > start + size;
+        let data = comments
+            .into_iter()
+            .skip(start)
+            .take(size)
+            .map(|comment| {
+                let reply = latest_replies
+                    .get(&comment.id)
+                    .map(|reply| reply.to_query(None));
+                comment.to_query(reply)
+            })
+            .collect();
+
+        (total, has_more, data)
+    }
+}
+
 pub mod message {
     use canistore_types::constant::CanisterType;
 
@@ -1,4 +1,5 @@
 use candid::Principal;
+use canistore_types::error::{CustomError, ErrorCode};
 use ic_ledger_types::{AccountIdentifier, Subaccount};
 use sha2::{Digest, Sha224};
 
@@ -87,6 +88,12 @@ pub fn check_page_size(page: usize, size: usize) -> (usize, usize) {
     (page, size)
 }
 
+pub fn parse_album_id(album_id: &str) -> Result<u64, String> {
+    album_id
+        .parse::<u64>()
+        .map_err(|_| CustomError::new(ErrorCode::ParamsError, Some("album_id")).to_string())
+}
+
 #[allow(dead_code)]
 pub fn vec_to_array_32(opt_vec: Option<Vec<u8>>) -> Option<[u8; 32]> {
     opt_vec.and_then(|vec| {
@@ -115,3 +122,99 @@ pub fn new_subaccount(opt_vec: Option<[u8; 32]>) -> Option<Subaccount> {
         None => None,
     }
 }
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// Days since the unix epoch of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = (if days >= 0 { days } else { days - 146_096 }) / 146_097;
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Start and end of a calendar month in UTC, in nanoseconds
+pub fn month_range(year: u32, month: u32) -> Option<(u64, u64)> {
+    if year < 1970 || month == 0 || month > 12 {
+        return None;
+    }
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    let to_nanos = |year: u32, month: u32| {
+        days_from_civil(year as i64, month, 1) as u64 * SECONDS_PER_DAY * NANOS_PER_SECOND
+    };
+    Some((to_nanos(year, month), to_nanos(next_year, next_month)))
+}
+
+// Formats a timestamp in nanoseconds as "YYYY-MM-DD HH:MM:SS" UTC
+pub fn format_datetime(nanos: u64) -> String {
+    let seconds = nanos / NANOS_PER_SECOND;
+    let (year, month, day) = civil_from_days((seconds / SECONDS_PER_DAY) as i64);
+    let seconds = seconds % SECONDS_PER_DAY;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = NANOS_PER_SECOND;
+
+    #[test]
+    fn month_range_works() {
+        assert_eq!(month_range(1970, 1), Some((0, 31 * 86_400 * SECOND)));
+        assert_eq!(
+            month_range(2024, 2),
+            Some((1_706_745_600 * SECOND, 1_709_251_200 * SECOND))
+        );
+        assert_eq!(
+            month_range(2023, 12),
+            Some((1_701_388_800 * SECOND, 1_704_067_200 * SECOND))
+        );
+        assert_eq!(
+            month_range(2000, 3).map(|(start, _)| start),
+            Some(951_868_800 * SECOND)
+        );
+
+        assert_eq!(month_range(1969, 12), None);
+        assert_eq!(month_range(2024, 0), None);
+        assert_eq!(month_range(2024, 13), None);
+    }
+
+    #[test]
+    fn format_datetime_works() {
+        assert_eq!(format_datetime(0), "1970-01-01 00:00:00");
+        assert_eq!(
+            format_datetime(1_709_251_199 * SECOND + 999),
+            "2024-02-29 23:59:59"
+        );
+    }
+}
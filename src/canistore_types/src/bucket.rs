@@ -27,6 +27,14 @@ pub struct BucketInfo {
     pub trusted_ecdsa_pub_keys: Vec<ByteBuf>,
     // used to verify the request token signed with ED25519
     pub trusted_eddsa_pub_keys: Vec<ByteN<32>>,
+    // trusted frontends or platform canisters that can report file analytics
+    pub reporters: BTreeSet<Principal>,
+    // origins allowed by the HTTP gateway CORS policy, "*" allows any origin
+    pub allowed_origins: Vec<String>,
+    // request headers allowed by CORS preflights, empty for the default headers
+    pub allowed_headers: Vec<String>,
+    // response headers exposed to CORS requests, empty for the default headers
+    pub exposed_headers: Vec<String>,
 }
 
 #[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
@@ -36,6 +44,52 @@ pub struct Token {
     pub policies: String,    // the permission policies
 }
 
+pub const SNAPSHOT_VERSION: u16 = 1;
+pub const MAX_ALLOWED_ORIGINS: usize = 20;
+pub const MAX_CORS_HEADERS: usize = 20;
+
+// origin should be "*" or in the form of "scheme://host[:port]"
+pub fn valid_origin(origin: &str) -> bool {
+    if origin == "*" {
+        return true;
+    }
+
+    match origin.split_once("://") {
+        Some((scheme, host)) => {
+            (scheme == "https" || scheme == "http")
+                && !host.is_empty()
+                && !host.contains(['/', '?', '#', ' '])
+        }
+        None => false,
+    }
+}
+
+// header should be "*" or a header name token
+pub fn valid_header_name(header: &str) -> bool {
+    header == "*"
+        || (!header.is_empty()
+            && header
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)))
+}
+
+// the position of a paginated snapshot export, sections are exported in this order.
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum SnapshotCursor {
+    Bucket,
+    Folders(u32),         // the next folder id
+    HashIndex(ByteN<32>), // the next file hash
+    Files(u32),           // the next file id
+    Chunks(u32, u32),     // the next (file id, chunk index)
+    Stats(u32, u32),      // the next (file id, day)
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize)]
+pub struct SnapshotPage {
+    pub data: ByteBuf,                // versioned snapshot section in CBOR
+    pub next: Option<SnapshotCursor>, // None: the export is completed
+}
+
 #[derive(CandidType, Clone, Debug, Default, Deserialize, Serialize)]
 pub struct UpdateBucketInput {
     pub name: Option<String>,
@@ -48,6 +102,9 @@ pub struct UpdateBucketInput {
     pub visibility: Option<u8>, // 0: private; 1: public
     pub trusted_ecdsa_pub_keys: Option<Vec<ByteBuf>>,
     pub trusted_eddsa_pub_keys: Option<Vec<ByteN<32>>>,
+    pub allowed_origins: Option<Vec<String>>,
+    pub allowed_headers: Option<Vec<String>>,
+    pub exposed_headers: Option<Vec<String>>,
 }
 
 impl UpdateBucketInput {
@@ -98,6 +155,39 @@ impl UpdateBucketInput {
                 return Err("visibility should be 0 or 1".to_string());
             }
         }
+
+        if let Some(allowed_origins) = &self.allowed_origins {
+            if allowed_origins.len() > MAX_ALLOWED_ORIGINS {
+                return Err(format!(
+                    "allowed_origins length should be less than or equal to {}",
+                    MAX_ALLOWED_ORIGINS
+                ));
+            }
+            for origin in allowed_origins {
+                if !valid_origin(origin) {
+                    return Err(format!("invalid origin: {}", origin));
+                }
+            }
+        }
+
+        for (field, headers) in [
+            ("allowed_headers", &self.allowed_headers),
+            ("exposed_headers", &self.exposed_headers),
+        ] {
+            if let Some(headers) = headers {
+                if headers.len() > MAX_CORS_HEADERS {
+                    return Err(format!(
+                        "{} length should be less than or equal to {}",
+                        field, MAX_CORS_HEADERS
+                    ));
+                }
+                for header in headers {
+                    if !valid_header_name(header) {
+                        return Err(format!("invalid header: {}", header));
+                    }
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -160,6 +160,44 @@ pub struct MoveInput {
     pub to: u32,
 }
 
+pub const MAX_FILE_STATS_EVENTS: usize = 1000; // max events per report call
+pub const MAX_FILE_STATS_DAYS: u32 = 366; // max days per time-series query
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FileStatEvent {
+    pub id: u32,
+    pub plays: u32,
+    pub downloads: u32,
+    pub timestamp: Option<u64>, // unix timestamp in milliseconds, default to now
+}
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FileDailyStat {
+    pub day: u32, // days since unix epoch
+    pub plays: u64,
+    pub downloads: u64,
+}
+
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FileStatsSummary {
+    pub id: u32,
+    pub plays: u64,
+    pub downloads: u64,
+}
+
+pub fn validate_file_stat_events(events: &[FileStatEvent]) -> Result<(), String> {
+    if events.is_empty() {
+        return Err("events cannot be empty".to_string());
+    }
+    if events.len() > MAX_FILE_STATS_EVENTS {
+        return Err(format!(
+            "events length should be less than or equal to {}",
+            MAX_FILE_STATS_EVENTS
+        ));
+    }
+    Ok(())
+}
+
 pub struct UrlFileParam {
     pub file: u32,
     pub hash: Option<ByteN<32>>,
@@ -168,14 +206,25 @@ pub struct UrlFileParam {
     pub inline: bool,
 }
 
+fn parse_url(req_url: &str) -> Result<Url, String> {
+    let url = if req_url.starts_with('/') {
+        Url::parse(format!("http://localhost{}", req_url).as_str())
+    } else {
+        Url::parse(req_url)
+    };
+    url.map_err(|_| format!("invalid url: {}", req_url))
+}
+
+fn decode_url_token(value: &str) -> Result<ByteBuf, String> {
+    let data = general_purpose::URL_SAFE_NO_PAD
+        .decode(value.as_bytes())
+        .map_err(|_| format!("failed to decode base64 token from {}", value))?;
+    Ok(ByteBuf::from(data))
+}
+
 impl UrlFileParam {
     pub fn from_url(req_url: &str) -> Result<Self, String> {
-        let url = if req_url.starts_with('/') {
-            Url::parse(format!("http://localhost{}", req_url).as_str())
-        } else {
-            Url::parse(req_url)
-        };
-        let url = url.map_err(|_| format!("invalid url: {}", req_url))?;
+        let url = parse_url(req_url)?;
         let mut path_segments = url
             .path_segments()
             .ok_or_else(|| format!("invalid url path: {}", req_url))?;
@@ -208,10 +257,7 @@ impl UrlFileParam {
         for (key, value) in url.query_pairs() {
             match key.as_ref() {
                 "token" => {
-                    let data = general_purpose::URL_SAFE_NO_PAD
-                        .decode(value.as_bytes())
-                        .map_err(|_| format!("failed to decode base64 token from {}", value))?;
-                    param.token = Some(ByteBuf::from(data));
+                    param.token = Some(decode_url_token(&value)?);
                     break;
                 }
                 "filename" => {
@@ -233,6 +279,76 @@ impl UrlFileParam {
     }
 }
 
+pub enum UrlUploadParam {
+    // POST /upload?parent=<id>&filename=<name>&size=<size>&token=<token>
+    Create {
+        parent: u32,
+        name: String,
+        size: Option<u64>,
+        token: Option<ByteBuf>,
+    },
+    // PUT /f/<id>/<chunk_index>?token=<token>
+    Chunk {
+        id: u32,
+        chunk_index: u32,
+        token: Option<ByteBuf>,
+    },
+}
+
+impl UrlUploadParam {
+    pub fn from_url(method: &str, req_url: &str) -> Result<Self, String> {
+        let url = parse_url(req_url)?;
+        let mut path_segments = url
+            .path_segments()
+            .ok_or_else(|| format!("invalid url path: {}", req_url))?;
+
+        let mut token: Option<ByteBuf> = None;
+        let mut parent: Option<u32> = None;
+        let mut name: Option<String> = None;
+        let mut size: Option<u64> = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "token" => {
+                    token = Some(decode_url_token(&value)?);
+                }
+                "parent" => {
+                    parent = Some(value.parse().map_err(|_| "invalid parent id")?);
+                }
+                "filename" => {
+                    name = Some(value.to_string());
+                }
+                "size" => {
+                    size = Some(value.parse().map_err(|_| "invalid file size")?);
+                }
+                _ => {}
+            }
+        }
+
+        match (method.to_ascii_uppercase().as_str(), path_segments.next()) {
+            ("POST", Some("upload")) => Ok(Self::Create {
+                parent: parent.unwrap_or_default(),
+                name: name.ok_or("missing filename")?,
+                size,
+                token,
+            }),
+            ("PUT", Some("f")) => Ok(Self::Chunk {
+                id: path_segments
+                    .next()
+                    .unwrap_or_default()
+                    .parse()
+                    .map_err(|_| "invalid file id")?,
+                chunk_index: path_segments
+                    .next()
+                    .unwrap_or_default()
+                    .parse()
+                    .map_err(|_| "invalid chunk index")?,
+                token,
+            }),
+            _ => Err(format!("invalid upload request: {} {}", method, req_url)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,4 +386,41 @@ mod tests {
         assert!(!valid_file_parent("test/file.txt"));
         assert!(!valid_file_parent("/file/"));
     }
+
+    #[test]
+    fn url_upload_param_works() {
+        match UrlUploadParam::from_url("POST", "/upload?parent=2&filename=a.mp3&size=64&token=AQID")
+            .unwrap()
+        {
+            UrlUploadParam::Create {
+                parent,
+                name,
+                size,
+                token,
+            } => {
+                assert_eq!(parent, 2);
+                assert_eq!(name, "a.mp3");
+                assert_eq!(size, Some(64));
+                assert_eq!(token.unwrap().as_slice(), &[1u8, 2, 3]);
+            }
+            _ => panic!("expected create param"),
+        }
+
+        match UrlUploadParam::from_url("put", "/f/5/1").unwrap() {
+            UrlUploadParam::Chunk {
+                id,
+                chunk_index,
+                token,
+            } => {
+                assert_eq!(id, 5);
+                assert_eq!(chunk_index, 1);
+                assert!(token.is_none());
+            }
+            _ => panic!("expected chunk param"),
+        }
+
+        assert!(UrlUploadParam::from_url("POST", "/upload?parent=2").is_err());
+        assert!(UrlUploadParam::from_url("PUT", "/f/5").is_err());
+        assert!(UrlUploadParam::from_url("GET", "/f/5/1").is_err());
+    }
 }
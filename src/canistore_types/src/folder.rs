@@ -14,6 +14,7 @@ pub struct FolderInfo {
     pub status: i8,             // -1: archived; 0: readable and writable; 1: readonly
     pub files: BTreeSet<u32>,   // length <= max_children
     pub folders: BTreeSet<u32>, // length <= max_children
+    pub visibility: Option<u8>, // 0: private; 1: public; None: inherit from the parent folder
 }
 
 #[derive(CandidType, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
@@ -49,6 +50,7 @@ pub struct UpdateFolderInput {
     pub id: u32,
     pub name: Option<String>,
     pub status: Option<i8>, // when set to 1, the file must be fully filled, and hash must be provided
+    pub visibility: Option<u8>, // 0: private; 1: public; 2: inherit from the parent folder
 }
 
 impl UpdateFolderInput {
@@ -64,6 +66,12 @@ impl UpdateFolderInput {
                 return Err("status should be -1, 0 or 1".to_string());
             }
         }
+
+        if let Some(visibility) = self.visibility {
+            if visibility > 2 {
+                return Err("visibility should be 0, 1 or 2".to_string());
+            }
+        }
         Ok(())
     }
 }
@@ -1,7 +1,14 @@
 use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
 
-use crate::space::{Album, Track};
+use crate::{
+    error::{CustomError, ErrorCode},
+    payment::TokenPrice,
+    space::{Album, Track},
+};
+
+pub const LICENSE_USAGE_ACTIONS: [&str; 3] = ["play", "download", "sync"];
+pub const MAX_LICENSE_USAGE_EVENTS: usize = 100; // max events per report call
 
 #[derive(CandidType, Clone, Deserialize, Serialize, Debug, Eq, PartialEq, Hash)]
 pub struct LicenseKey {
@@ -67,13 +74,39 @@ pub enum RightPeriod {
     Perpetuity,
 }
 
+impl RightPeriod {
+    // Duration in nanoseconds, None for perpetual rights
+    pub fn duration(&self) -> Option<u64> {
+        const YEAR: u64 = 365 * 86400 * 1_000_000_000;
+        match self {
+            RightPeriod::Months12 => Some(YEAR),
+            RightPeriod::Years3 => Some(3 * YEAR),
+            RightPeriod::Years5 => Some(5 * YEAR),
+            RightPeriod::Years10 => Some(10 * YEAR),
+            RightPeriod::Perpetuity => None,
+        }
+    }
+
+    // The longest of the granted periods applies, no period means perpetual rights.
+    pub fn valid_duration(periods: &[RightPeriod]) -> Option<u64> {
+        let mut duration = None;
+        for period in periods {
+            match period.duration() {
+                Some(d) => duration = Some(duration.unwrap_or(0).max(d)),
+                None => return None,
+            }
+        }
+        duration
+    }
+}
+
 #[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
 pub struct License {
     pub id: u64,                     // License ID
     pub user: Principal,             // Licensed user
     pub resource_key: LicenseKey,    // License resource key (album_id, track_id)
     pub start_time: u64,             // License start time
-    pub valid_duration: Option<u64>, // License validity duration (in seconds)
+    pub valid_duration: Option<u64>, // License validity duration (in nanoseconds)
     pub revoke_time: Option<u64>,    // License revocation time (optional)
     pub channel: ChannelType,        // License channel (e.g., application, website)
     pub asset_type: Vec<AssetType>,
@@ -83,21 +116,58 @@ pub struct License {
     pub right_period: Vec<RightPeriod>,
     pub fee: Option<u128>,
     pub created: u64,
+    pub expired_time: Option<u64>, // Set by the expiry sweep once the license has lapsed
+    pub token_prices: Option<Vec<TokenPrice>>, // Prices in other tokens, `fee` is the ICP price
 }
 
 impl License {
     pub fn is_active(&self, current_time: u64) -> bool {
         // Check if the license has been revoked
         if let Some(revoke_time) = self.revoke_time {
-            return current_time < revoke_time;
+            if current_time >= revoke_time {
+                return false;
+            }
         }
 
         // If valid_duration is None, the license is considered perpetual (no expiry)
-        match self.valid_duration {
-            Some(duration) => current_time < self.start_time + duration,
+        match self.expire_time() {
+            Some(expire_time) => current_time < expire_time,
             None => true,
         }
     }
+
+    // Price of the license in the given token, ICP falls back to the default license price.
+    pub fn price_in(&self, token: &str, default_icp_price: u64) -> Option<u64> {
+        if token == "ICP" {
+            return Some(self.fee.map_or(default_icp_price, |fee| fee as u64));
+        }
+        self.token_prices
+            .as_ref()?
+            .iter()
+            .find(|price| price.token_name == token)
+            .map(|price| price.price)
+    }
+
+    pub fn expire_time(&self) -> Option<u64> {
+        self.valid_duration
+            .map(|duration| self.start_time.saturating_add(duration))
+    }
+
+    // Extends the license by one more right period, counted from its expiry or from now if lapsed.
+    pub fn renew(&mut self, current_time: u64) -> Result<u64, String> {
+        let (expire_time, duration) = match (
+            self.expire_time(),
+            RightPeriod::valid_duration(&self.right_period),
+        ) {
+            (Some(expire_time), Some(duration)) => (expire_time, duration),
+            _ => return Err("License is perpetual and cannot be renewed".to_string()),
+        };
+
+        let new_expire_time = expire_time.max(current_time) + duration;
+        self.valid_duration = Some(new_expire_time - self.start_time);
+        self.expired_time = None;
+        Ok(new_expire_time)
+    }
 }
 
 #[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
@@ -122,6 +192,8 @@ pub struct QueryLicenseResp {
     pub right_period: Vec<RightPeriod>,
     pub fee: Option<u128>,
     pub created: u64,
+    pub expired_time: Option<u64>,
+    pub token_prices: Option<Vec<TokenPrice>>,
     pub source: LicenseSource,
 }
 
@@ -142,6 +214,8 @@ impl QueryLicenseResp {
             right_period: license.right_period,
             fee: license.fee,
             created: license.created,
+            expired_time: license.expired_time,
+            token_prices: license.token_prices,
             source,
         }
     }
@@ -208,13 +282,28 @@ pub struct CreateTrackLicenseArg {
     pub fee: Option<u128>,
 }
 
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct CreateAlbumLicenseArg {
+    pub album_id: u64,
+    pub user_pid: Principal,
+    pub channel: ChannelType,
+    pub asset_type: Vec<AssetType>,
+    pub usage_rights: Vec<UsageRights>,
+    pub licensed_media: Vec<LicensedMedia>,
+    pub licensed_territory: Vec<LicensedTerritory>,
+    pub right_period: Vec<RightPeriod>,
+    pub fee: Option<u128>,
+}
+
 #[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
 pub struct LicenseRecord {
-    pub id: u64,          // License usage record ID
-    pub license_id: u64,  // Corresponding license ID
-    pub user: Principal,  // User who used the license
-    pub access_time: u64, // Access time
-    pub action: String,   // Action performed (e.g., play, download)
+    pub id: u64,                     // License usage record ID
+    pub license_id: u64,             // Corresponding license ID
+    pub user: Principal,             // User who used the license
+    pub access_time: u64,            // Access time
+    pub action: String,              // Action performed (e.g., play, download)
+    pub quantity: Option<u64>,       // Number of uses covered by the record, 1 if None
+    pub reporter: Option<Principal>, // Platform that reported on behalf of the user
 }
 
 impl LicenseRecord {
@@ -231,6 +320,181 @@ impl LicenseRecord {
             user,
             access_time,
             action,
+            quantity: None,
+            reporter: None,
+        }
+    }
+
+    pub fn quantity(&self) -> u64 {
+        self.quantity.unwrap_or(1)
+    }
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct LicenseUsageArg {
+    pub license_id: u64,
+    pub action: String,
+    pub quantity: Option<u64>,
+    pub access_time: Option<u64>, // Defaults to the time of the report
+}
+
+impl LicenseUsageArg {
+    pub fn validate(&self) -> Result<(), String> {
+        if !LICENSE_USAGE_ACTIONS.contains(&self.action.as_str()) {
+            return Err(CustomError::new(ErrorCode::ParamsError, Some("action")).to_string());
+        }
+        if self.quantity == Some(0) {
+            return Err(CustomError::new(ErrorCode::ParamsError, Some("quantity")).to_string());
         }
+        Ok(())
+    }
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug, Default)]
+pub struct LicenseUsageStat {
+    pub action: String,
+    pub events: u64,
+    pub quantity: u64,
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct LicenseUsageReport {
+    pub license_id: u64,
+    pub user: Principal,
+    pub resource_key: LicenseKey,
+    pub total_events: u64,
+    pub total_quantity: u64,
+    pub actions: Vec<LicenseUsageStat>,
+    pub first_access: Option<u64>,
+    pub last_access: Option<u64>,
+}
+
+impl LicenseUsageReport {
+    pub fn new(license: &License, records: &[LicenseRecord]) -> Self {
+        let mut actions: Vec<LicenseUsageStat> = Vec::new();
+        for record in records {
+            let index = match actions.iter().position(|stat| stat.action == record.action) {
+                Some(index) => index,
+                None => {
+                    actions.push(LicenseUsageStat {
+                        action: record.action.clone(),
+                        ..Default::default()
+                    });
+                    actions.len() - 1
+                }
+            };
+            actions[index].events += 1;
+            actions[index].quantity += record.quantity();
+        }
+
+        LicenseUsageReport {
+            license_id: license.id,
+            user: license.user,
+            resource_key: license.resource_key.clone(),
+            total_events: records.len() as u64,
+            total_quantity: records.iter().map(LicenseRecord::quantity).sum(),
+            actions,
+            first_access: records.iter().map(|r| r.access_time).min(),
+            last_access: records.iter().map(|r| r.access_time).max(),
+        }
+    }
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct TrackUsageReport {
+    pub track_id: u64,
+    pub total_events: u64,
+    pub total_quantity: u64,
+    pub licenses: Vec<LicenseUsageReport>,
+}
+
+impl TrackUsageReport {
+    pub fn new(track_id: u64, licenses: Vec<LicenseUsageReport>) -> Self {
+        TrackUsageReport {
+            track_id,
+            total_events: licenses.iter().map(|l| l.total_events).sum(),
+            total_quantity: licenses.iter().map(|l| l.total_quantity).sum(),
+            licenses,
+        }
+    }
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct QueryLicenseRecordResp {
+    pub page: usize,
+    pub total: usize,
+    pub has_more: bool,
+    pub data: Vec<LicenseRecord>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YEAR: u64 = 365 * 86400 * 1_000_000_000;
+
+    fn license(start_time: u64, right_period: Vec<RightPeriod>) -> License {
+        License {
+            id: 1,
+            user: Principal::anonymous(),
+            resource_key: LicenseKey {
+                album_id: None,
+                track_id: Some(1),
+            },
+            start_time,
+            valid_duration: RightPeriod::valid_duration(&right_period),
+            revoke_time: None,
+            channel: ChannelType::User,
+            asset_type: vec![],
+            usage_rights: vec![],
+            licensed_media: vec![],
+            licensed_territory: vec![],
+            right_period,
+            fee: None,
+            created: start_time,
+            expired_time: None,
+            token_prices: None,
+        }
+    }
+
+    #[test]
+    fn valid_duration_works() {
+        assert_eq!(RightPeriod::valid_duration(&[]), None);
+        assert_eq!(
+            RightPeriod::valid_duration(&[RightPeriod::Months12]),
+            Some(YEAR)
+        );
+        assert_eq!(
+            RightPeriod::valid_duration(&[RightPeriod::Months12, RightPeriod::Years5]),
+            Some(5 * YEAR)
+        );
+        assert_eq!(
+            RightPeriod::valid_duration(&[RightPeriod::Years3, RightPeriod::Perpetuity]),
+            None
+        );
+    }
+
+    #[test]
+    fn renew_works() {
+        let start = 1_000;
+
+        // renewed before expiry, the period is added to the current expiry
+        let mut active = license(start, vec![RightPeriod::Months12]);
+        assert_eq!(active.renew(start + YEAR / 2), Ok(start + 2 * YEAR));
+        assert_eq!(active.valid_duration, Some(2 * YEAR));
+        assert!(active.is_active(start + YEAR + 1));
+
+        // renewed after expiry, the period is counted from now
+        let mut lapsed = license(start, vec![RightPeriod::Months12]);
+        lapsed.expired_time = Some(start + YEAR);
+        let now = start + 3 * YEAR;
+        assert_eq!(lapsed.renew(now), Ok(now + YEAR));
+        assert_eq!(lapsed.expire_time(), Some(now + YEAR));
+        assert_eq!(lapsed.expired_time, None);
+        assert!(lapsed.is_active(now + 1));
+
+        let mut perpetual = license(start, vec![RightPeriod::Perpetuity]);
+        assert!(perpetual.renew(start).is_err());
+        assert_eq!(perpetual.valid_duration, None);
     }
 }
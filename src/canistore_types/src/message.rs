@@ -172,3 +172,11 @@ pub struct MsgShareTrack {
     pub duration: Option<u64>,
     pub created: u64,
 }
+
+#[derive(CandidType, Clone, Deserialize, Serialize, Debug)]
+pub struct MsgFollowSpace {
+    pub space_id: Principal,
+    pub user_pid: Principal,
+    pub follow: bool, // false when the user unfollows the space
+    pub created_at: u64,
+}
@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 
 pub const SPACE_CREATION_PRICE_ICP: u64 = 100_000_000;
 pub const SPACE_LICENSE_PRICE_ICP: u64 = 100_000_000;
+pub const REVENUE_SHARE_TOTAL_RATIO: u32 = 10_000; // ratios are in basis points
+pub const MAX_REVENUE_SHARES: usize = 20;
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub enum PaymentType {
@@ -37,6 +39,61 @@ pub struct PaymentOrder {
     pub verified_time: Option<u64>,
     pub shared_time: Option<u64>,
     pub created_time: u64,
+    #[serde(default)]
+    pub delivered_time: Option<u64>, // set once the purchased goods have been granted
+    #[serde(default)]
+    pub refund_tx: Option<u64>, // ledger index of the refund transfer
+    #[serde(default)]
+    pub refunded_amount: Option<u64>, // amount sent back to the payer, the deposit less the fee
+    #[serde(default)]
+    pub swept_time: Option<u64>, // last time the stale order sweep checked the order
+    #[serde(default)]
+    pub consolidated_time: Option<u64>, // set once the earnings were moved to the treasury
+    #[serde(default)]
+    pub consolidation_tried_time: Option<u64>, // last time the treasury sweep tried the order
+    #[serde(default)]
+    pub revenue_shares: Option<Vec<RevenueShare>>, // split sheet that applied when the order was paid
+}
+
+pub const PAYMENT_ORDER_TIMEOUT: u64 = 15 * 60 * 1_000_000_000; // 15 minutes to pay an order
+pub const STALE_ORDER_GRACE: u64 = 2 * PAYMENT_ORDER_TIMEOUT; // lets in-flight confirmations settle
+pub const STALE_ORDER_RECHECK: u64 = 30 * 86400 * 1_000_000_000; // late deposits are refunded for 30 days
+pub const STALE_ORDER_SWEEP_INTERVAL_SECS: u64 = 600;
+pub const MAX_STALE_ORDERS_PER_SWEEP: usize = 50;
+
+impl PaymentOrder {
+    // Unsettled orders past the grace period, and timed out orders that may still receive a late deposit
+    pub fn needs_sweep(&self, now: u64) -> bool {
+        match self.status {
+            PaymentStatus::Unpaid | PaymentStatus::Verifying => {
+                self.created_time + STALE_ORDER_GRACE < now
+            }
+            PaymentStatus::TimedOut => {
+                self.refund_tx.is_none() && self.created_time + STALE_ORDER_RECHECK > now
+            }
+            _ => false,
+        }
+    }
+}
+
+// Pays an order from an ICRC-2 allowance instead of a deposit to the order subaccount
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct Icrc2Checkout {
+    pub from_subaccount: Option<Vec<u8>>,
+}
+
+impl Icrc2Checkout {
+    // The caller pays from its own account, which must have approved the canister.
+    pub fn account(&self, owner: Principal) -> Result<Account, String> {
+        let subaccount = match &self.from_subaccount {
+            Some(subaccount) => Some(
+                <[u8; 32]>::try_from(subaccount.as_slice())
+                    .map_err(|_| "Invalid from_subaccount".to_string())?,
+            ),
+            None => None,
+        };
+        Ok(Account { owner, subaccount })
+    }
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -104,6 +161,14 @@ impl SubscriptionType {
 pub struct SubscriptionPrice {
     pub sub_type: SubscriptionType,
     pub price: u64,
+    #[serde(default)]
+    pub token_name: Option<String>, // None prices the subscription in ICP
+}
+
+impl SubscriptionPrice {
+    pub fn token(&self) -> &str {
+        self.token_name.as_deref().unwrap_or("ICP")
+    }
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -111,6 +176,8 @@ pub struct LicensePrice {
     pub track_id: u64,
     pub token_name: String,
     pub price: u64,
+    pub license_id: Option<u64>, // Set when the order renews an existing license
+    pub album_id: Option<u64>,   // Set when the order licenses a whole album
 }
 
 impl LicensePrice {
@@ -119,6 +186,22 @@ impl LicensePrice {
             track_id,
             token_name: "ICP".to_string(),
             price,
+            license_id: None,
+            album_id: None,
+        }
+    }
+
+    pub fn new_for_album_license(album_id: u64, price: u64) -> Self {
+        LicensePrice {
+            album_id: Some(album_id),
+            ..Self::new_for_license(0, price)
+        }
+    }
+
+    pub fn new_for_renewal(track_id: u64, license_id: u64, price: u64) -> Self {
+        LicensePrice {
+            license_id: Some(license_id),
+            ..Self::new_for_license(track_id, price)
         }
     }
 }
@@ -138,6 +221,41 @@ impl TokenPrice {
     }
 }
 
+pub const BUILTIN_TOKENS: [&str; 2] = ["ICP", "CKBTC"];
+pub const MAX_SPACE_TOKENS: usize = 20;
+pub const TOKEN_FEE_REFRESH_INTERVAL_SECS: u64 = 6 * 3600;
+
+// An ICRC-1 token accepted by a space, in addition to the builtin tokens
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TokenInfo {
+    pub symbol: String,
+    pub ledger_id: Principal,
+    pub decimals: u8,
+    pub fee: u64, // fetched from icrc1_fee
+    pub enabled: bool,
+    pub updated: u64,
+}
+
+impl TokenInfo {
+    pub fn is_builtin(symbol: &str) -> bool {
+        BUILTIN_TOKENS
+            .iter()
+            .any(|token| token.eq_ignore_ascii_case(symbol))
+    }
+}
+
+pub const TREASURY_SWEEP_INTERVAL_SECS: u64 = 3600;
+// Earnings are kept apart from the default account, which canister_transfer moves freely
+pub const TREASURY_SUBACCOUNT: [u8; 32] = *b"canistore_space_treasury\0\0\0\0\0\0\0\0";
+pub const MAX_ORDERS_PER_CONSOLIDATION: usize = 50;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TreasuryBalance {
+    pub token: String,
+    pub balance: u64,        // held by the treasury account
+    pub unconsolidated: u64, // paid into order subaccounts and not yet swept
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct Subscriber {
     pub pid: Principal,
@@ -189,6 +307,29 @@ pub struct AwardOrder {
     pub created_time: u64,
 }
 
+impl AwardOrder {
+    pub fn from_payment_order(order: &PaymentOrder, album_id: String, created_time: u64) -> Self {
+        AwardOrder {
+            id: order.id,
+            album_id,
+            contributor: order.payer,
+            token: order.token.clone(),
+            amount: order.amount_paid,
+            created_time,
+        }
+    }
+}
+
+// Aggregated awards of one contributor, used by the supporters leaderboard
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct AwardSupporter {
+    pub contributor: Principal,
+    pub token: String,
+    pub amount: u64,
+    pub count: u32,
+    pub last_awarded: u64,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct RevenueShare {
     pub ratio: u32,
@@ -196,6 +337,88 @@ pub struct RevenueShare {
     pub remarks: String,
 }
 
+// Amounts of different tokens are never summed, each token keeps its own total
+pub fn add_token_amount(totals: &mut Vec<(String, u64)>, token: &str, amount: u64) {
+    match totals.iter_mut().find(|(name, _)| name == token) {
+        Some((_, total)) => *total = total.saturating_add(amount),
+        None => totals.push((token.to_string(), amount)),
+    }
+}
+
+// A split sheet must name distinct recipients whose ratios add up to 100%.
+pub fn validate_revenue_shares(shares: &[RevenueShare]) -> Result<(), String> {
+    if shares.len() > MAX_REVENUE_SHARES {
+        return Err(format!(
+            "revenue shares exceed the limit {}",
+            MAX_REVENUE_SHARES
+        ));
+    }
+    let mut total: u32 = 0;
+    for (i, share) in shares.iter().enumerate() {
+        if share.ratio == 0 {
+            return Err(format!(
+                "revenue share ratio of {} is zero",
+                share.recipient
+            ));
+        }
+        if share.recipient == Principal::anonymous() {
+            return Err("revenue share recipient is anonymous".to_string());
+        }
+        if shares[..i].iter().any(|s| s.recipient == share.recipient) {
+            return Err(format!(
+                "duplicate revenue share recipient {}",
+                share.recipient
+            ));
+        }
+        total = total.saturating_add(share.ratio);
+    }
+    if total != REVENUE_SHARE_TOTAL_RATIO {
+        return Err(format!(
+            "revenue share ratios sum to {}, expected {}",
+            total, REVENUE_SHARE_TOTAL_RATIO
+        ));
+    }
+    Ok(())
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub enum PayoutStatus {
+    Queued, // recorded when the order is paid, not attempted yet
+    Pending,
+    Paid,
+    Failed,
+    Skipped, // the share does not cover the transfer fee
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PayoutRecord {
+    pub order_id: u64,
+    pub recipient: Principal,
+    pub ratio: u32,
+    pub token: String,
+    pub amount: u64, // transferred amount, after the ledger fee
+    pub status: PayoutStatus,
+    pub block_height: Option<u64>,
+    pub error: Option<String>,
+    pub created: u64,
+    pub updated: u64,
+}
+
+impl PayoutRecord {
+    // Queued payouts are not attempted yet, failed ones are retried
+    pub fn is_unpaid(&self) -> bool {
+        self.status == PayoutStatus::Queued || self.status == PayoutStatus::Failed
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QueryPayoutResp {
+    pub page: usize,
+    pub total: usize,
+    pub has_more: bool,
+    pub data: Vec<PayoutRecord>,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct SubscriberInfo {
     pub pid: Principal,
@@ -205,6 +428,35 @@ pub struct SubscriberInfo {
     pub is_black: bool,
 }
 
+impl SubscriberInfo {
+    pub fn new(subscriber: Subscriber) -> Self {
+        Self {
+            pid: subscriber.pid,
+            sub_type: subscriber.sub_type,
+            expire_time: subscriber.expire_time,
+            created: subscriber.created,
+            is_black: false,
+        }
+    }
+
+    pub fn is_active(&self, now: u64) -> bool {
+        !self.is_black && self.expire_time > now
+    }
+
+    // Extends the subscription following `Subscriber::update_subscription`.
+    pub fn extend(&mut self, new_subscriber: &Subscriber) {
+        let mut subscriber = Subscriber {
+            pid: self.pid,
+            created: self.created,
+            sub_type: self.sub_type.clone(),
+            expire_time: self.expire_time,
+        };
+        subscriber.update_subscription(new_subscriber);
+        self.sub_type = subscriber.sub_type;
+        self.expire_time = subscriber.expire_time;
+    }
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub enum QuerySort {
     TimeDesc,
@@ -245,6 +497,8 @@ pub struct QueryOrder {
     pub status: PaymentStatus,
     pub verified_time: Option<u64>,
     pub shared_time: Option<u64>,
+    pub refund_tx: Option<u64>,
+    pub refunded_amount: Option<u64>,
 }
 
 impl QueryOrder {
@@ -262,6 +516,8 @@ impl QueryOrder {
             status: order.status,
             verified_time: order.verified_time,
             shared_time: order.shared_time,
+            refund_tx: order.refund_tx,
+            refunded_amount: order.refunded_amount,
         }
     }
 }
@@ -291,6 +547,20 @@ pub struct QueryAward {
     pub created: u64,
 }
 
+impl From<&AwardOrder> for QueryAward {
+    fn from(order: &AwardOrder) -> Self {
+        QueryAward {
+            id: order.id,
+            album_id: order.album_id.clone(),
+            contributor: order.contributor,
+            token: order.token.clone(),
+            amount: order.amount,
+            created: order.created_time,
+        }
+    }
+}
+
+// An empty album_id queries the awards of the whole space
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct QueryAwardReq {
     pub album_id: String,
@@ -307,6 +577,119 @@ pub struct QueryAwardResp {
     pub data: Vec<QueryAward>,
 }
 
+// Accounts of the space journal, every entry debits one account and credits another
+#[derive(
+    CandidType, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug,
+)]
+pub enum JournalAccount {
+    OrderEscrow, // funds held in order subaccounts
+    Treasury,
+    Revenue,
+    LedgerFees,
+    Payouts, // collaborator revenue splits
+    Refunds, // deposits owed back to payers
+    Withdrawals,
+}
+
+impl JournalAccount {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalAccount::OrderEscrow => "order_escrow",
+            JournalAccount::Treasury => "treasury",
+            JournalAccount::Revenue => "revenue",
+            JournalAccount::LedgerFees => "ledger_fees",
+            JournalAccount::Payouts => "payouts",
+            JournalAccount::Refunds => "refunds",
+            JournalAccount::Withdrawals => "withdrawals",
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub enum JournalKind {
+    OrderPaid,
+    Fee,
+    Payout,
+    Refund,
+    Consolidation,
+    Withdrawal,
+}
+
+impl JournalKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalKind::OrderPaid => "order_paid",
+            JournalKind::Fee => "fee",
+            JournalKind::Payout => "payout",
+            JournalKind::Refund => "refund",
+            JournalKind::Consolidation => "consolidation",
+            JournalKind::Withdrawal => "withdrawal",
+        }
+    }
+}
+
+// Amounts are in the smallest unit of the token
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct JournalEntry {
+    pub id: u64,
+    pub kind: JournalKind,
+    pub order_id: Option<u64>,
+    pub token: String,
+    pub amount: u64,
+    pub debit: JournalAccount,
+    pub credit: JournalAccount,
+    pub block_height: Option<u64>,
+    pub created: u64,
+}
+
+impl JournalEntry {
+    pub const CSV_HEADER: &'static str =
+        "id,date,kind,order_id,token,amount,debit,credit,block_height";
+
+    pub fn to_csv_row(&self, date: &str) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.id,
+            date,
+            self.kind.as_str(),
+            self.order_id.map_or(String::new(), |id| id.to_string()),
+            self.token,
+            self.amount,
+            self.debit.as_str(),
+            self.credit.as_str(),
+            self.block_height
+                .map_or(String::new(), |height| height.to_string()),
+        )
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct JournalBalance {
+    pub token: String,
+    pub account: JournalAccount,
+    pub debit: u64,
+    pub credit: u64,
+}
+
+// The period is [start, end) in nanoseconds, an end of 0 is unbounded
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QueryJournalReq {
+    pub token: Option<String>,
+    pub start: u64,
+    pub end: u64,
+    pub page: u32,
+    pub size: u32,
+    pub sort: QuerySort,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QueryJournalResp {
+    pub page: u32,
+    pub total: u32,
+    pub has_more: bool,
+    pub data: Vec<JournalEntry>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct TransferArgs {
     pub recipient: Vec<u8>,
@@ -321,3 +704,94 @@ pub struct ICRC1TransferArgs {
     pub recipient: Account,
     pub memo: Option<Vec<u8>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn share(id: u8, ratio: u32) -> RevenueShare {
+        RevenueShare {
+            ratio,
+            recipient: Principal::from_slice(&[id]),
+            remarks: String::new(),
+        }
+    }
+
+    #[test]
+    fn validate_revenue_shares_works() {
+        assert!(validate_revenue_shares(&[share(1, 10_000)]).is_ok());
+        assert!(validate_revenue_shares(&[share(1, 7_000), share(2, 3_000)]).is_ok());
+
+        assert!(validate_revenue_shares(&[]).is_err());
+        assert!(validate_revenue_shares(&[share(1, 7_000), share(2, 2_000)]).is_err());
+        assert!(validate_revenue_shares(&[share(1, 7_000), share(2, 4_000)]).is_err());
+        assert!(validate_revenue_shares(&[share(1, 10_000), share(2, 0)]).is_err());
+        assert!(validate_revenue_shares(&[share(1, 5_000), share(1, 5_000)]).is_err());
+        assert!(validate_revenue_shares(&[
+            share(1, 5_000),
+            RevenueShare {
+                recipient: Principal::anonymous(),
+                ..share(2, 5_000)
+            }
+        ])
+        .is_err());
+        assert!(validate_revenue_shares(&[share(1, u32::MAX), share(2, 10_001)]).is_err());
+
+        let shares: Vec<RevenueShare> = (0..=MAX_REVENUE_SHARES as u8)
+            .map(|id| share(id + 1, 1))
+            .collect();
+        assert!(validate_revenue_shares(&shares).is_err());
+    }
+
+    fn order(status: PaymentStatus, created_time: u64) -> PaymentOrder {
+        PaymentOrder {
+            id: 1,
+            payer: Principal::anonymous(),
+            amount: 100,
+            payment_type: PaymentType::Verification(false),
+            source: String::new(),
+            token: "ICP".to_string(),
+            amount_paid: 0,
+            status,
+            verified_time: None,
+            shared_time: None,
+            created_time,
+            delivered_time: None,
+            refund_tx: None,
+            refunded_amount: None,
+            swept_time: None,
+            consolidated_time: None,
+            consolidation_tried_time: None,
+            revenue_shares: None,
+        }
+    }
+
+    #[test]
+    fn needs_sweep_works() {
+        let created = 1_000;
+        let after_grace = created + STALE_ORDER_GRACE + 1;
+
+        for status in [PaymentStatus::Unpaid, PaymentStatus::Verifying] {
+            assert!(!order(status.clone(), created).needs_sweep(created + PAYMENT_ORDER_TIMEOUT));
+            assert!(!order(status.clone(), created).needs_sweep(created + STALE_ORDER_GRACE));
+            assert!(order(status, created).needs_sweep(after_grace));
+        }
+
+        let timed_out = order(PaymentStatus::TimedOut, created);
+        assert!(timed_out.needs_sweep(after_grace));
+        assert!(!timed_out.needs_sweep(created + STALE_ORDER_RECHECK));
+        let refunded = PaymentOrder {
+            refund_tx: Some(1),
+            ..timed_out
+        };
+        assert!(!refunded.needs_sweep(after_grace));
+
+        for status in [
+            PaymentStatus::Paid,
+            PaymentStatus::Refunded,
+            PaymentStatus::Cancelled,
+        ] {
+            assert!(!order(status, created).needs_sweep(after_grace));
+        }
+    }
+}
@@ -1,3 +1,4 @@
+#[cfg(not(test))]
 use ic_cdk::api::time;
 use std::cmp::Ordering;
 
@@ -6,13 +7,24 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     constant::Environment,
+    error::{CustomError, ErrorCode},
     message::{MessageSource, MsgShareTrack, MsgUserPost},
-    payment::{SubscriberInfo, SubscriptionPrice},
+    payment::{RevenueShare, SubscriberInfo, SubscriptionPrice},
     user::Attribute,
 };
 
+// the canister time is not available in unit tests
+#[cfg(test)]
+fn time() -> u64 {
+    0
+}
+
 const DEFAULT_OSS_MAX_FILE_SIZE: u64 = 300 * 1024 * 1024 * 1024;
 pub const SPACE_FEE: u64 = 10_000;
+pub const MAX_COMMENT_LENGTH: usize = 1000;
+pub const MAX_CATEGORIES: usize = 500;
+pub const MAX_CATEGORY_NAME_LENGTH: usize = 64;
+pub const MAX_TRACK_SIDE_LENGTH: usize = 8;
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub enum CanisterArgs {
@@ -140,12 +152,39 @@ pub enum MusicCategory {
     Other,
 }
 
+impl MusicCategory {
+    // Ids start at 1, 0 stands for any category in album filters.
+    pub fn id(&self) -> u32 {
+        self.clone() as u32 + 1
+    }
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct Category {
     pub id: u32,
     pub category_type: MusicCategory,
     pub desc: String,
     pub parent: Option<u32>,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct CategoryArgs {
+    pub name: String,
+    pub desc: String,
+    pub category_type: MusicCategory,
+    pub parent: Option<u32>,
+}
+
+impl CategoryArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        let name = self.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_CATEGORY_NAME_LENGTH {
+            return Err(CustomError::new(ErrorCode::ParamsError, Some("name")).to_string());
+        }
+        Ok(())
+    }
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, PartialOrd)]
@@ -197,6 +236,12 @@ pub struct Album {
     pub subscriber_count: u32,
     pub subscribers: Vec<SubscriberInfo>,
     pub album_stat: AlbumStat,
+    pub category_id: Option<u32>, // custom category of the space
+    pub revenue_shares: Option<Vec<RevenueShare>>,
+    pub total_awards: Option<u32>,
+    pub total_income: Option<Vec<(String, u64)>>, // per token
+    pub release_channel_id: Option<u64>, // platform channel the tracks are shared to on release
+    pub release_status: Option<AlbumStatus>, // status the album gets on release, Public when unset
 
     pub created: u64,
     pub updated: u64,
@@ -253,6 +298,12 @@ impl Album {
             subscriber_count: 0,
             subscribers: vec![],
             album_stat: AlbumStat::default(),
+            category_id: None,
+            revenue_shares: None,
+            total_awards: None,
+            total_income: None,
+            release_channel_id: None,
+            release_status: None,
             created: current_time,
             updated: current_time,
             toped: 0,
@@ -376,12 +427,24 @@ pub struct Track {
     pub cert_key: Option<String>,
     pub cert_hex: Option<String>,
     pub attributes: Vec<Attribute>,
+    pub category_id: Option<u32>, // custom category of the space
+    pub disc_number: Option<u32>, // Disc of a multi-disc release, starting at 1
+    pub side: Option<String>,     // Vinyl or cassette side, e.g. "A"
+    pub revenue_shares: Option<Vec<RevenueShare>>,
 
     pub created: u64,
     pub updated: u64,
 }
 
 impl Track {
+    /// Returns the track without its revenue splits, for callers outside the space team.
+    pub fn public_view(self) -> Self {
+        Track {
+            revenue_shares: None,
+            ..self
+        }
+    }
+
     pub fn to_msg(
         &self,
         user_pid: Principal,
@@ -442,9 +505,25 @@ pub struct EditTrackArg {
     pub duration: Option<u64>,
     pub file_size: Option<u64>,
     pub file_format: Option<String>,
+    pub disc_number: Option<u32>,
+    pub side: Option<String>,
 }
 
-#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+impl EditTrackArg {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.disc_number == Some(0) {
+            return Err(CustomError::new(ErrorCode::ParamsError, Some("disc_number")).to_string());
+        }
+        if let Some(side) = &self.side {
+            if side.is_empty() || side.chars().count() > MAX_TRACK_SIDE_LENGTH {
+                return Err(CustomError::new(ErrorCode::ParamsError, Some("side")).to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub enum CommentStatus {
     Invisible,
     Visible,
@@ -461,6 +540,25 @@ pub struct Comment {
     pub likes: u32,
     pub status: CommentStatus,
     pub reply: Option<Box<Comment>>,
+    pub parent_id: Option<u32>, // None for a top-level comment, otherwise the thread root
+    pub reply_count: u32,
+}
+
+impl Comment {
+    pub fn to_query(&self, reply: Option<QueryComment>) -> QueryComment {
+        QueryComment {
+            id: self.id,
+            album_id: self.album_id.clone(),
+            commenter_id: self.commenter_id,
+            content: self.content.clone(),
+            likes: self.likes,
+            status: self.status.clone(),
+            created: self.created,
+            reply: reply.map(Box::new),
+            parent_id: self.parent_id,
+            reply_count: self.reply_count,
+        }
+    }
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -480,6 +578,16 @@ impl Album {
     }
 }
 
+impl From<&Album> for SortedAlbum {
+    fn from(album: &Album) -> Self {
+        SortedAlbum {
+            id: album.id.to_string(),
+            toped: album.toped,
+            created: album.created,
+        }
+    }
+}
+
 impl SortedAlbum {
     pub fn compare_albums_desc(a: &SortedAlbum, b: &SortedAlbum) -> Ordering {
         if a.toped > 0 && b.toped > 0 {
@@ -520,6 +628,10 @@ impl Default for Track {
             cert_key: None,
             cert_hex: None,
             attributes: vec![],
+            category_id: None,
+            disc_number: None,
+            side: None,
+            revenue_shares: None,
 
             created: current_time,
             updated: current_time,
@@ -558,9 +670,9 @@ impl Album {
         Ok(self.track_ids.remove(index))
     }
 
-    /// Moves a track ID from one position to another. Returns an error if any position is invalid.
+    /// Moves a track ID from one position to another, `to` being its final position. Returns an error if any position is invalid.
     pub fn move_track(&mut self, from: usize, to: usize) -> Result<(), String> {
-        if from >= self.track_ids.len() || to > self.track_ids.len() {
+        if from >= self.track_ids.len() || to >= self.track_ids.len() {
             return Err(format!(
                 "Move positions out of bounds: from {} to {} for track_ids of length {}",
                 from,
@@ -568,7 +680,7 @@ impl Album {
                 self.track_ids.len()
             ));
         }
-        if from == to || from + 1 == to {
+        if from == to {
             // No change needed
             return Ok(());
         }
@@ -577,10 +689,34 @@ impl Album {
         Ok(())
     }
 
+    /// Replaces the track order. `track_ids` must contain exactly the tracks of the album.
+    pub fn reorder_tracks(&mut self, track_ids: Vec<u64>) -> Result<(), String> {
+        let mut current = self.track_ids.clone();
+        let mut requested = track_ids.clone();
+        current.sort_unstable();
+        requested.sort_unstable();
+        if current != requested {
+            return Err("Track ids do not match the tracks of the album".to_string());
+        }
+        self.track_ids = track_ids;
+        Ok(())
+    }
+
     /// Returns the number of tracks in the album.
     pub fn track_count(&self) -> usize {
         self.track_ids.len()
     }
+
+    /// Returns the album without subscriber and revenue details, for callers outside the space team.
+    pub fn public_view(self) -> Self {
+        Album {
+            subscribers: vec![],
+            revenue_shares: None,
+            total_income: None,
+            release_channel_id: None,
+            ..self
+        }
+    }
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -589,6 +725,48 @@ pub struct BlacklistedUser {
     pub created: u64,
 }
 
+// Managers only act in the areas of their roles, the owner can do everything
+#[derive(
+    CandidType, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug,
+)]
+pub enum ManagerRole {
+    Editor,    // albums, tracks, categories and licenses
+    Finance,   // orders, statements and withdrawals
+    Moderator, // comments and blacklist
+    Publisher, // shares, posts and releases
+}
+
+impl ManagerRole {
+    // Managers added before roles existed only keep catalog access
+    pub fn legacy() -> Vec<ManagerRole> {
+        vec![ManagerRole::Editor]
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QueryManager {
+    pub pid: Principal,
+    pub roles: Vec<ManagerRole>,
+}
+
+// Roles are the ones held after the change, empty when the manager was removed
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RoleAuditEntry {
+    pub id: u64,
+    pub operator: Principal,
+    pub pid: Principal,
+    pub roles: Vec<ManagerRole>,
+    pub created: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QueryRoleAuditResp {
+    pub page: u32,
+    pub total: u32,
+    pub has_more: bool,
+    pub data: Vec<RoleAuditEntry>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct QueryCategory {
     pub id: u32,
@@ -664,6 +842,76 @@ pub struct QueryAlbum {
     pub copyright: Option<String>,
 }
 
+impl From<&Album> for QueryAlbum {
+    fn from(album: &Album) -> Self {
+        QueryAlbum {
+            id: album.id.to_string(),
+            album_type: album.album_type.clone(),
+            title: album.title.clone(),
+            cover_image: album.cover_image.clone(),
+            producer: album.producer,
+            description: album.description.clone(),
+            category: album.category.id(),
+            sub_category: album.sub_category.as_ref().map_or(0, |c| c.id()),
+            created: album.created,
+            updated: album.updated,
+            toped: album.toped,
+            status: album.status.clone(),
+            allow_comments: album.allow_comments,
+            likes: album.likes,
+            dislikes: album.dislikes,
+            plays: album.plays,
+            comment_count: album.comments,
+            new_comment_count: 0,
+            is_original: album.is_original,
+            external_link: album.external_link.clone(),
+            tags: album.tags.clone(),
+            copyright: album.copyright.clone(),
+        }
+    }
+}
+
+impl QueryAlbumReq {
+    // Checks every filter except `status`, which is counted separately in `AlbumStat`.
+    pub fn matches(&self, album: &Album) -> bool {
+        if self.category > 0 && album.category.id() != self.category {
+            return false;
+        }
+        if self.sub_category > 0
+            && album.sub_category.as_ref().map_or(0, |c| c.id()) != self.sub_category
+        {
+            return false;
+        }
+        if let Some(album_type) = &self.album_type {
+            if &album.album_type != album_type {
+                return false;
+            }
+        }
+
+        let search = self.search.trim().to_lowercase();
+        search.is_empty()
+            || album.title.to_lowercase().contains(&search)
+            || album.artist.to_lowercase().contains(&search)
+            || album
+                .tags
+                .iter()
+                .any(|tag| tag.to_lowercase().contains(&search))
+    }
+}
+
+impl AlbumStat {
+    pub fn add(&mut self, status: &AlbumStatus) {
+        match status {
+            AlbumStatus::Public => self.public_count += 1,
+            AlbumStatus::Private => self.private_count += 1,
+            AlbumStatus::Subscription => self.subscription_count += 1,
+            AlbumStatus::Draft => self.draft_count += 1,
+            AlbumStatus::Deleted => return,
+        }
+        self.total += 1;
+    }
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct QueryDetailResp {
     pub result: Result<QueryAlbum, String>,
@@ -712,7 +960,9 @@ pub struct QueryComment {
     pub likes: u32,
     pub status: CommentStatus,
     pub created: u64,
-    pub reply: Option<Box<QueryComment>>,
+    pub reply: Option<Box<QueryComment>>, // latest visible reply of a top-level comment
+    pub parent_id: Option<u32>,
+    pub reply_count: u32,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -720,6 +970,7 @@ pub struct QueryCommentReq {
     pub page: u32,
     pub size: u32,
     pub album_id: String,
+    pub parent_id: Option<u32>, // None lists top-level comments, Some lists the replies of a thread
     pub commenter_id: Option<Principal>,
     pub sort: QuerySort,
 }
@@ -732,6 +983,14 @@ pub struct QueryCommentResp {
     pub data: Vec<QueryComment>,
 }
 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct QueryFollowerResp {
+    pub page: usize,
+    pub total: usize,
+    pub has_more: bool,
+    pub data: Vec<Principal>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct QueryCommonReq {
     pub page: u32,
@@ -805,6 +1064,9 @@ pub struct QueryTrackResp {
     pub has_share_store: bool,          // Whether a share_store has been created for the track
     pub cert_key: Option<String>,       // Certification key
     pub cert_hex: Option<String>,       // Certification hex
+    pub disc_number: Option<u32>,       // Disc of a multi-disc release
+    pub side: Option<String>,           // Vinyl or cassette side
+    pub track_number: u32,              // Position in the album tracklist, 0 if unknown
 
     pub created: u64, // Creation time
     pub updated: u64, // Last update time
@@ -836,6 +1098,9 @@ impl QueryTrackResp {
             has_share_store,
             cert_key: track.cert_key,
             cert_hex: track.cert_hex,
+            disc_number: track.disc_number,
+            side: track.side,
+            track_number: 0,
             created: track.created,
             updated: track.updated,
         }
@@ -898,3 +1163,70 @@ impl UserPost {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn album_with_tracks(track_ids: Vec<u64>) -> Album {
+        let mut album = Album::new(
+            1,
+            AlbumType::Album,
+            "Album".to_string(),
+            "Artist".to_string(),
+            String::new(),
+            Principal::anonymous(),
+            String::new(),
+            MusicCategory::Rock,
+            None,
+            true,
+            String::new(),
+            vec![],
+            "en".to_string(),
+            None,
+            None,
+            vec![],
+        );
+        album.track_ids = track_ids;
+        album
+    }
+
+    #[test]
+    fn move_track_works() {
+        let mut album = album_with_tracks(vec![1, 2, 3, 4]);
+
+        album.move_track(0, 2).unwrap();
+        assert_eq!(album.track_ids, vec![2, 3, 1, 4]);
+
+        album.move_track(3, 0).unwrap();
+        assert_eq!(album.track_ids, vec![4, 2, 3, 1]);
+
+        album.move_track(1, 3).unwrap();
+        assert_eq!(album.track_ids, vec![4, 3, 1, 2]);
+
+        album.move_track(2, 2).unwrap();
+        assert_eq!(album.track_ids, vec![4, 3, 1, 2]);
+
+        assert!(album.move_track(4, 0).is_err());
+        assert!(album.move_track(0, 4).is_err());
+        assert_eq!(album.track_ids, vec![4, 3, 1, 2]);
+    }
+
+    #[test]
+    fn reorder_tracks_works() {
+        let mut album = album_with_tracks(vec![1, 2, 3]);
+
+        album.reorder_tracks(vec![3, 1, 2]).unwrap();
+        assert_eq!(album.track_ids, vec![3, 1, 2]);
+
+        assert!(album.reorder_tracks(vec![3, 1]).is_err());
+        assert!(album.reorder_tracks(vec![3, 1, 2, 4]).is_err());
+        assert!(album.reorder_tracks(vec![3, 1, 1]).is_err());
+        assert!(album.reorder_tracks(vec![3, 1, 5]).is_err());
+        assert_eq!(album.track_ids, vec![3, 1, 2]);
+
+        let mut empty = album_with_tracks(vec![]);
+        assert!(empty.reorder_tracks(vec![]).is_ok());
+        assert!(empty.reorder_tracks(vec![1]).is_err());
+    }
+}
@@ -49,6 +49,7 @@ fn init(args: Option<CanisterArgs>) {
                 state_ref.user_count = 0;
             });
             store::state::save();
+            store::payment::set_stale_order_sweep_timer();
         }
         Some(CanisterArgs::Upgrade(_)) => {
             ic_cdk::trap(
@@ -99,4 +100,7 @@ fn post_upgrade(args: Option<CanisterArgs>) {
             // No arguments provided; continue with the existing state
         }
     }
+
+    store::user::rebuild_space_index();
+    store::payment::set_stale_order_sweep_timer();
 }
@@ -67,6 +67,16 @@ fn get_email(user: Option<Principal>) -> String {
     }
 }
 
+#[query]
+fn get_followed_spaces(user: Option<Principal>) -> Vec<Principal> {
+    let uid = user.unwrap_or_else(ic_cdk::caller);
+
+    match store::user::get_user(uid) {
+        Some(user_wrapper) => user_wrapper.into_inner().subscribes,
+        None => Vec::new(),
+    }
+}
+
 #[query]
 fn get_user_spaces(user: Option<Principal>) -> Vec<UserSpaceInfo> {
     let uid = user.unwrap_or_else(ic_cdk::caller);
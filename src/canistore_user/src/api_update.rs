@@ -1,11 +1,11 @@
 use crate::{
-    guards::{anonymous_guard, owner_guard},
+    guards::{anonymous_guard, owner_guard, space_guard},
     MAX_CREATE_SPACE_SIZE,
 };
 use candid::Principal;
 use canistore_types::{
     error::{CustomError, ErrorCode},
-    payment::{PaymentInfo, PaymentType, TokenPrice},
+    payment::{Icrc2Checkout, PaymentInfo, PaymentType, TokenPrice},
     space::{CanisterArgs, OssCanisterArgs, OssInitArgs, SpaceInitArgs, SpaceOssCanisterArgs},
     user::{Attribute, UpdateUserInfo, User, UserInfo, UserSpaceInfo},
     ByteN,
@@ -257,6 +257,41 @@ fn set_user_info(update_info: UpdateUserInfo) -> Result<bool, String> {
     }
 }
 
+// Space canisters report follows as they happen, so the followed spaces of a user
+// always match the followers of the space.
+#[ic_cdk::update(guard = "space_guard")]
+fn update_followed_space(user: Principal, follow: bool) -> Result<bool, String> {
+    set_followed_space(user, ic_cdk::caller(), follow)
+}
+
+// Returns true if the followed spaces of the user changed, fans without a profile are skipped.
+fn set_followed_space(
+    user_pid: Principal,
+    space_id: Principal,
+    follow: bool,
+) -> Result<bool, String> {
+    if store::user::get_user(user_pid).is_none() {
+        return Ok(false);
+    }
+
+    let mut changed = false;
+    store::user::update_user(user_pid, |user| {
+        let pos = user.subscribes.iter().position(|pid| pid == &space_id);
+        match (follow, pos) {
+            (true, None) => user.subscribes.push(space_id),
+            (false, Some(pos)) => {
+                user.subscribes.remove(pos);
+            }
+            _ => return,
+        }
+        changed = true;
+        user.updated_at = ic_cdk::api::time();
+    })
+    .map_err(|_| CustomError::new(ErrorCode::DataUpdateError, Some("User Follows")).to_string())?;
+
+    Ok(changed)
+}
+
 // #[update(guard = "anonymous_guard")]
 // async fn create_user_space() -> Result<Principal, String> {
 //     create_user_space_core().await
@@ -301,10 +336,7 @@ fn add_user_space_info(user_pid: Principal, space_info: UserSpaceInfo) -> Result
 
     match user_wrapper {
         Some(_user_wrapper) => {
-            store::user::update_user(user_pid, |user| {
-                user.spaces.push(space_info);
-            })
-            .map_err(|_| {
+            store::user::add_space_to_user(user_pid, space_info).map_err(|_| {
                 CustomError::new(ErrorCode::DataUpdateError, Some("User space info")).to_string()
             })?;
 
@@ -343,9 +375,15 @@ fn create_payment_order(source: String) -> Result<Option<PaymentInfo>, String> {
 }
 
 #[ic_cdk::update(guard = "anonymous_guard")]
-async fn confirm_payment_order(pay_id: u64) -> Result<bool, String> {
-    let result = store::payment::confirm_payment_order(pay_id).await;
-    result
+async fn confirm_payment_order(
+    pay_id: u64,
+    checkout: Option<Icrc2Checkout>,
+) -> Result<bool, String> {
+    // With an ICRC-2 checkout the caller pays from its own approved account.
+    let from = checkout
+        .map(|checkout| checkout.account(caller()))
+        .transpose()?;
+    store::payment::confirm_payment_order(pay_id, from).await
 }
 
 #[ic_cdk::update(guard = "anonymous_guard")]
@@ -5,7 +5,7 @@ use crate::api_cycles::WalletReceiveResult;
 use crate::api_init::CanisterArgs;
 use canistore_types::{
     canister::{StatusRequest, StatusResponse},
-    payment::{PaymentInfo, QueryCommonReq, QueryOrderResp},
+    payment::{Icrc2Checkout, PaymentInfo, QueryCommonReq, QueryOrderResp},
     user::{Attribute, UpdateUserInfo, UserInfo, UserSpaceInfo},
     ByteN,
 };
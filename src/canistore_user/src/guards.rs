@@ -1,4 +1,4 @@
-use crate::store::state;
+use crate::store::{self, state};
 use candid::Principal;
 
 #[inline(always)]
@@ -20,3 +20,12 @@ pub fn owner_guard() -> Result<(), String> {
         Err("Error: Only the owner can call this action.".to_string())
     }
 }
+
+#[inline(always)]
+pub fn space_guard() -> Result<(), String> {
+    if store::user::is_user_space(ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err("Error: Only space canisters can call this action.".to_string())
+    }
+}
@@ -9,6 +9,7 @@ use ic_ledger_types::{
 };
 use icrc_ledger_types::icrc1::account::Account;
 use icrc_ledger_types::icrc1::transfer::TransferArg as ICRC1TransferArg;
+use icrc_ledger_types::icrc2::transfer_from::{TransferFromArgs, TransferFromError};
 use std::convert::TryFrom;
 
 // Helper function to convert Nat to u64
@@ -55,10 +56,10 @@ pub async fn icrc1_transfer(
     to: Account,
     amount: u64,
 ) -> Result<u64, String> {
-    let fee = token_fee(token);
+    // The ledger applies its current fee
     let args = ICRC1TransferArg {
         to,
-        fee: Some(Nat::from(fee)),
+        fee: None,
         memo: None,
         from_subaccount: from,
         created_at_time: None,
@@ -98,6 +99,43 @@ pub async fn icrc1_transfer(
     }
 }
 
+// Pulls funds approved by the owner of `from` with ICRC-2 transfer_from
+pub async fn icrc2_transfer_from(
+    token: &str,
+    from: Account,
+    to: Account,
+    amount: u64,
+) -> Result<u64, String> {
+    let env = state::get_env();
+    let ledger_canister_id = match token {
+        "ICP" => env.get_canister_pid(CanisterType::Ledger),
+        "CKBTC" => env.get_canister_pid(CanisterType::CkBTCLedger),
+        _ => return Err("Unsupported token".to_string()),
+    };
+    let ledger_canister_id = Principal::from_text(ledger_canister_id).unwrap();
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from,
+        to,
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+
+    let result: Result<(Result<Nat, TransferFromError>,), (RejectionCode, String)> =
+        call(ledger_canister_id, "icrc2_transfer_from", (args,)).await;
+
+    match result {
+        Ok((Ok(height),)) => Ok(nat_to_u64(height)),
+        Ok((Err(err),)) => Err(format!("ledger transfer_from error {:?}", err)),
+        Err((rejection_code, err_msg)) => Err(format!(
+            "Canister call failed: {:?}, {}",
+            rejection_code, err_msg
+        )),
+    }
+}
+
 // Function to handle ICP transfers using the Ledger canister
 pub async fn transfer_icp(
     from_subaccount: Option<Subaccount>,
@@ -132,6 +170,14 @@ pub fn token_fee(token: &str) -> u64 {
     }
 }
 
+// Encodes the default account of a principal as expected by `token_transfer`
+pub fn token_recipient(token: &str, owner: Principal) -> Vec<u8> {
+    match token {
+        "ICP" => account_id(owner, None).as_ref().to_vec(),
+        _ => owner.as_slice().to_vec(),
+    }
+}
+
 // Main function to handle token transfer logic
 pub async fn token_transfer(
     token: &str,
@@ -104,6 +104,7 @@ impl PaymentOrderWrapper {
 const STATE_MEMORY_ID: MemoryId = MemoryId::new(0);
 const USER_MEMORY_ID: MemoryId = MemoryId::new(1);
 const PAYMENT_MEMORY_ID: MemoryId = MemoryId::new(2);
+const SPACE_INDEX_MEMORY_ID: MemoryId = MemoryId::new(3);
 
 thread_local! {
     static STATE: RefCell<State> = RefCell::new(State::default());
@@ -129,6 +130,13 @@ thread_local! {
             MEMORY_MANAGER.with_borrow(|m| m.get(PAYMENT_MEMORY_ID)),
         )
     );
+
+    // space canister id -> owner, so space calls are checked without decoding every user
+    static SPACE_INDEX_STORE: RefCell<StableBTreeMap<Principal, Principal, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with_borrow(|m| m.get(SPACE_INDEX_MEMORY_ID)),
+        )
+    );
 }
 
 pub mod state {
@@ -264,10 +272,32 @@ pub mod user {
         })
     }
 
+    // Spaces created through this canister
+    pub fn is_user_space(space_id: Principal) -> bool {
+        SPACE_INDEX_STORE.with(|r| r.borrow().contains_key(&space_id))
+    }
+
+    // Indexes the spaces of users stored before the index existed
+    pub fn rebuild_space_index() {
+        USER_STORE.with(|users| {
+            SPACE_INDEX_STORE.with(|index| {
+                let mut index = index.borrow_mut();
+                for (pid, user) in users.borrow().iter() {
+                    for space in user.into_inner().spaces {
+                        index.insert(space.space_id, pid);
+                    }
+                }
+            })
+        });
+    }
+
     pub fn add_space_to_user(pid: Principal, space_info: UserSpaceInfo) -> Result<(), String> {
+        let space_id = space_info.space_id;
         update_user(pid, |user| {
             user.spaces.push(space_info);
-        })
+        })?;
+        SPACE_INDEX_STORE.with(|r| r.borrow_mut().insert(space_id, pid));
+        Ok(())
     }
 }
 
@@ -275,14 +305,20 @@ pub mod payment {
     use std::time::Duration;
 
     use crate::{
-        pay::{default_account_id, token_balance, token_fee, token_transfer},
+        pay::{
+            default_account_id, icrc2_transfer_from, token_balance, token_fee, token_recipient,
+            token_transfer,
+        },
         utils::generate_order_subaccount,
     };
 
     use super::*;
     use crate::utils::check_page_size;
     use canistore_types::{
-        payment::{PaymentInfo, PaymentStatus, PaymentType, QueryCommonReq, QueryOrder, QuerySort},
+        payment::{
+            PaymentInfo, PaymentStatus, PaymentType, QueryCommonReq, QueryOrder, QuerySort,
+            MAX_STALE_ORDERS_PER_SWEEP, PAYMENT_ORDER_TIMEOUT, STALE_ORDER_SWEEP_INTERVAL_SECS,
+        },
         space::SPACE_FEE,
     };
     use ic_cdk::api::time;
@@ -334,6 +370,13 @@ pub mod payment {
             verified_time: None,
             shared_time: None,
             created_time: time(),
+            delivered_time: None,
+            refund_tx: None,
+            refunded_amount: None,
+            swept_time: None,
+            consolidated_time: None,
+            consolidation_tried_time: None,
+            revenue_shares: None,
         };
 
         PAYMENT_STORE.with(|store| {
@@ -397,7 +440,12 @@ pub mod payment {
         (total, has_more, data)
     }
 
-    pub async fn confirm_payment_order(order_id: u64) -> Result<bool, String> {
+    // With an ICRC-2 payer account the order amount is pulled from its allowance,
+    // otherwise the deposit to the order subaccount is verified.
+    pub async fn confirm_payment_order(
+        order_id: u64,
+        checkout: Option<Account>,
+    ) -> Result<bool, String> {
         let order = get_payment_order(order_id);
 
         let mut check_order = match order {
@@ -405,10 +453,23 @@ pub mod payment {
             None => return Err(format!("Order with id {} not found", order_id)),
         };
 
+        // Only the payer can pay the order from its allowance
+        if checkout.is_some_and(|from| from.owner != check_order.payer) {
+            return Err("Caller is not the payer of the order".to_string());
+        }
+
         // Verify the payment order
-        let is_verified = verify_payment_order(&mut check_order).await;
+        let result = match checkout {
+            Some(from) if check_order.status == PaymentStatus::Unpaid => {
+                pull_payment_order(&mut check_order, from).await
+            }
+            _ => match verify_payment_order(&mut check_order).await {
+                true => Ok(()),
+                false => Err("Order verification failed".to_string()),
+            },
+        };
 
-        if is_verified {
+        if result.is_ok() {
             share_pay(&mut check_order).await;
         }
 
@@ -418,10 +479,42 @@ pub mod payment {
             store.insert(order_id, PaymentOrderWrapper(check_order));
         });
 
-        if is_verified {
-            Ok(true)
-        } else {
-            Err("Order verification failed".to_string())
+        result.map(|_| true)
+    }
+
+    // The order is stored as verifying before the ledger call, so it is never charged twice.
+    async fn pull_payment_order(order: &mut PaymentOrder, from: Account) -> Result<(), String> {
+        if order.created_time + PAYMENT_ORDER_TIMEOUT < time() {
+            order.status = PaymentStatus::TimedOut;
+            return Err("Order has timed out".to_string());
+        }
+
+        order.status = PaymentStatus::Verifying;
+        PAYMENT_STORE.with(|store| {
+            store
+                .borrow_mut()
+                .insert(order.id, PaymentOrderWrapper(order.clone()))
+        });
+
+        let subaccount: Option<[u8; 32]> = generate_order_subaccount(order.payer, order.id)
+            .try_into()
+            .ok();
+        let to = Account {
+            owner: ic_cdk::id(),
+            subaccount,
+        };
+
+        match icrc2_transfer_from(&order.token, from, to, order.amount).await {
+            Ok(_) => {
+                order.amount_paid = order.amount;
+                order.status = PaymentStatus::Paid;
+                order.verified_time = Some(time());
+                Ok(())
+            }
+            Err(err) => {
+                order.status = PaymentStatus::Unpaid;
+                Err(err)
+            }
         }
     }
 
@@ -458,6 +551,105 @@ pub mod payment {
         }
     }
 
+    // Times out unsettled orders and refunds deposits that arrived too late. Orders checked
+    // least recently go first, so a backlog of stale orders is worked through over several runs.
+    pub async fn sweep_stale_orders(now: u64) -> usize {
+        let mut orders: Vec<PaymentOrder> = PAYMENT_STORE.with(|store| {
+            store
+                .borrow()
+                .iter()
+                .map(|(_, order)| order.into_inner())
+                .filter(|order| order.needs_sweep(now))
+                .collect()
+        });
+        orders.sort_by_key(|order| order.swept_time.unwrap_or(0));
+        orders.truncate(MAX_STALE_ORDERS_PER_SWEEP);
+
+        let mut refunded = 0;
+        for mut order in orders {
+            if refund_stale_order(&mut order, now).await {
+                refunded += 1;
+            }
+
+            PAYMENT_STORE.with(|store| {
+                let mut store = store.borrow_mut();
+                // Keep orders that were settled while the ledger was being called
+                if let Some(current) = store.get(&order.id) {
+                    if current.0.status == PaymentStatus::Paid
+                        || current.0.status == PaymentStatus::Refunded
+                    {
+                        return;
+                    }
+                }
+                store.insert(order.id, PaymentOrderWrapper(order));
+            });
+        }
+        refunded
+    }
+
+    // Settled orders are skipped, they may have been confirmed since the sweep listed them
+    fn is_stale_order(order_id: u64, now: u64) -> bool {
+        match get_payment_order(order_id) {
+            Some(order) => {
+                order.status != PaymentStatus::Paid
+                    && order.status != PaymentStatus::Refunded
+                    && order.created_time + PAYMENT_ORDER_TIMEOUT < now
+            }
+            None => false,
+        }
+    }
+
+    async fn refund_stale_order(order: &mut PaymentOrder, now: u64) -> bool {
+        order.swept_time = Some(now);
+        if !is_stale_order(order.id, now) {
+            return false;
+        }
+        order.status = PaymentStatus::TimedOut;
+
+        let subaccount: Option<[u8; 32]> = generate_order_subaccount(order.payer, order.id)
+            .try_into()
+            .ok();
+        let order_account = Account {
+            owner: ic_cdk::id(),
+            subaccount,
+        };
+        let balance = token_balance(&order.token, order_account).await;
+        let fee = token_fee(&order.token);
+        if balance <= fee || !is_stale_order(order.id, now) {
+            return false;
+        }
+
+        let refund_to = token_recipient(&order.token, order.payer);
+        match token_transfer(&order.token, subaccount, refund_to, balance - fee).await {
+            Ok(height) => {
+                order.amount_paid = balance;
+                order.refunded_amount = Some(balance - fee);
+                order.status = PaymentStatus::Refunded;
+                order.refund_tx = Some(height);
+                order.verified_time = Some(time());
+                true
+            }
+            Err(err) => {
+                ic_cdk::print(format!("Failed to refund order {}: {}", order.id, err));
+                false
+            }
+        }
+    }
+
+    pub fn set_stale_order_sweep_timer() {
+        ic_cdk_timers::set_timer_interval(
+            Duration::from_secs(STALE_ORDER_SWEEP_INTERVAL_SECS),
+            || {
+                ic_cdk::spawn(async {
+                    let count = sweep_stale_orders(time()).await;
+                    if count > 0 {
+                        ic_cdk::print(format!("Refunded {} stale orders", count));
+                    }
+                })
+            },
+        );
+    }
+
     async fn verify_payment_order(order: &mut PaymentOrder) -> bool {
         // If order is already paid, return true
         if order.status == PaymentStatus::Paid {
@@ -472,9 +664,8 @@ pub mod payment {
         // Update the status to verifying
         order.status = PaymentStatus::Verifying;
 
-        // Set timeout for 15 minutes (converted to nanoseconds)
-        let fifteen_minutes = Duration::from_secs(15 * 60).as_nanos() as u64;
-        if order.created_time + fifteen_minutes < time() {
+        // Orders must be paid within PAYMENT_ORDER_TIMEOUT
+        if order.created_time + PAYMENT_ORDER_TIMEOUT < time() {
             order.status = PaymentStatus::TimedOut;
             return false;
         }
@@ -575,9 +766,10 @@ pub mod payment {
 
         // Attempt to transfer the refund
         match token_transfer(&order.token, subaccount, refund_to, amount_to_refund).await {
-            Ok(_) => {
+            Ok(height) => {
                 // Update order status to refunded
                 order.status = PaymentStatus::Refunded;
+                order.refund_tx = Some(height);
                 order.verified_time = Some(time());
                 true
             }